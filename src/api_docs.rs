@@ -7,13 +7,54 @@ use utoipa::{
 #[derive(OpenApi)]
 #[openapi(
     paths(
-        handler::model::calculate_volume
+        handler::model::calculate_volume,
+        handler::nesting::nest,
+        handler::preview::preview,
+        handler::render::thumbnail,
+        handler::render::cross_section
     ),
     components(
         schemas(
             // calculate_volume
             models::mdl::CalculateVolumeReq,
             models::mdl::CalculateVolumeRes,
+            models::mdl::VolumeRes,
+            models::mdl::CalculateArchiveRes,
+            models::mdl::ArchiveFileRes,
+            models::mdl::PrintSettingsReq,
+            models::mdl::AdhesionReq,
+            models::mdl::EstimateRes,
+            models::mdl::LineItemRes,
+            models::mdl::BridgeRes,
+            models::mdl::CavityRes,
+            models::mdl::DrainHoleRes,
+            models::mdl::HollowReq,
+            models::mdl::HollowRes,
+            models::mdl::BuildItemRes,
+            models::mdl::HullRes,
+            models::mdl::MaterialCostRes,
+            models::mdl::MaterialRes,
+            models::mdl::QuoteReq,
+            models::mdl::QuoteRes,
+            models::mdl::ScaleReq,
+            models::mdl::TransformReq,
+
+            // nest
+            models::mdl::NestingReq,
+            models::mdl::NestingItem,
+            models::mdl::NestingRes,
+            models::mdl::PlacementRes,
+
+            // preview
+            models::mdl::PreviewReq,
+
+            // thumbnail
+            models::mdl::ThumbnailReq,
+
+            // cross_section
+            models::mdl::CrossSectionReq,
+            models::mdl::CrossSectionRes,
+            models::mdl::SectionRes,
 
             // generic error response
             models::error::ResponseError,
@@ -0,0 +1,121 @@
+// Bed adhesion features
+//
+// Everything is derived from the first layer of the model. The brim grows outwards
+// from every outer contour, the skirt loops and the raft follow the convex hull of
+// the whole footprint. Offsetting a convex outline of perimeter P and area A by d
+// gives an area of A + P*d + PI*d^2 and a perimeter of P + 2*PI*d, for concave
+// outlines these slightly overestimate, which is the safe side for a quote.
+
+use std::f32::consts::PI;
+
+use crate::calculate::{
+    nesting::Footprint,
+    slice::{self, Layer},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Adhesion {
+    /// brim width around the outer walls (mm), 0 disables the brim
+    pub brim_width: f32,
+    /// number of skirt loops, 0 disables the skirt
+    pub skirt_lines: u32,
+    /// gap between the part and the innermost skirt loop (mm)
+    pub skirt_distance: f32,
+    /// number of raft layers under the part, 0 disables the raft
+    pub raft_layers: u32,
+    /// distance the raft extends past the footprint (mm)
+    pub raft_margin: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Brim,
+    Skirt,
+    Raft,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Brim => "brim",
+            Self::Skirt => "skirt",
+            Self::Raft => "raft",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineItem {
+    pub kind: Kind,
+    /// extruded material (mm³)
+    pub material: f32,
+    /// print time (s)
+    pub time: f32,
+}
+
+/// Material and time of the enabled adhesion features, printed with the first layer
+/// height `layer_height`.
+pub fn adhesion(
+    first: &Layer,
+    layer_height: f32,
+    line_width: f32,
+    speed: f32,
+    settings: &Adhesion,
+) -> Vec<LineItem> {
+    if first.contours.is_empty() {
+        return Vec::new();
+    }
+
+    let flow = line_width * layer_height * speed;
+    let item = |kind, material: f32| LineItem {
+        kind,
+        material,
+        time: material / flow,
+    };
+
+    let hull = Footprint::from_points(
+        first
+            .contours
+            .iter()
+            .flatten()
+            .map(|p| [p[0] as f64, p[1] as f64])
+            .collect(),
+    );
+    let hull_perimeter = slice::length(&hull.outline);
+    let hull_area = slice::signed_area(&hull.outline).abs();
+
+    let mut items = Vec::new();
+
+    if settings.brim_width > 0.0 {
+        let w = settings.brim_width;
+        // outer contours run counter-clockwise, holes get no brim
+        let area: f32 = first
+            .contours
+            .iter()
+            .filter(|contour| slice::signed_area(contour) > 0.0)
+            .map(|contour| slice::length(contour) * w + PI * w * w)
+            .sum();
+        items.push(item(Kind::Brim, area * layer_height));
+    }
+
+    if settings.skirt_lines > 0 {
+        let length: f32 = (0..settings.skirt_lines)
+            .map(|i| {
+                let distance = settings.skirt_distance + (i as f32 + 0.5) * line_width;
+                hull_perimeter + 2.0 * PI * distance
+            })
+            .sum();
+        items.push(item(Kind::Skirt, length * line_width * layer_height));
+    }
+
+    if settings.raft_layers > 0 {
+        let m = settings.raft_margin;
+        let area = hull_area + hull_perimeter * m + PI * m * m;
+        items.push(item(
+            Kind::Raft,
+            area * layer_height * settings.raft_layers as f32,
+        ));
+    }
+
+    items
+}
@@ -0,0 +1,146 @@
+// Bridge detection
+//
+// A cell of a layer is unsupported when it lies further than half a line width from
+// anything printed in the layer below. Every connected unsupported region is then
+// checked for the supported cells it touches in its own layer: seen from the region's
+// centre these anchors are sorted into eight directions, and a region held from two
+// roughly opposite directions is a bridge, as long as the region lies between them:
+// the point halfway between two anchors must itself be unsupported. Regions held
+// from one side only are plain overhangs, and so are rings overhanging a supported
+// core, whose anchors face each other across the core. The span is the distance
+// between the closest pair of opposite anchors, which is the direction a slicer would
+// lay the bridge lines in.
+
+use std::f32::consts::TAU;
+
+use crate::calculate::raster::Mask;
+
+const SECTORS: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bridge {
+    /// height of the layer the bridge is printed in (mm)
+    pub z: f32,
+    /// unsupported area (mm²)
+    pub area: f32,
+    /// unsupported length between the anchors (mm)
+    pub span: f32,
+}
+
+/// Bridges printed in `upper` on top of `lower`, both on the same grid.
+pub fn bridges(lower: &Mask, upper: &Mask, z: f32, line_width: f32) -> Vec<Bridge> {
+    let unsupported = upper.difference(&lower.dilate(line_width / 2.0));
+    let cell_area = upper.grid.cell * upper.grid.cell;
+    // anything narrower than a line is not printed as a bridge of its own
+    let min_cells = ((line_width * line_width) / cell_area).ceil() as usize;
+
+    unsupported
+        .components()
+        .into_iter()
+        .filter(|region| region.len() >= min_cells.max(1))
+        .filter_map(|region| {
+            let centre = centroid(
+                &region
+                    .iter()
+                    .map(|&i| upper.position(i))
+                    .collect::<Vec<_>>(),
+            );
+
+            // sum and count of anchor positions per direction
+            let mut sectors = [([0.0f32; 2], 0usize); SECTORS];
+            for &index in region.iter() {
+                for neighbour in upper.neighbours(index) {
+                    if !upper.cells[neighbour] || unsupported.cells[neighbour] {
+                        continue;
+                    }
+                    let p = upper.position(neighbour);
+                    let angle = (p[1] - centre[1]).atan2(p[0] - centre[0]).rem_euclid(TAU);
+                    let sector = ((angle / TAU * SECTORS as f32) as usize).min(SECTORS - 1);
+                    sectors[sector].0[0] += p[0];
+                    sectors[sector].0[1] += p[1];
+                    sectors[sector].1 += 1;
+                }
+            }
+
+            let anchor = |s: usize| {
+                let (sum, count) = sectors[s % SECTORS];
+                (count > 0).then(|| [sum[0] / count as f32, sum[1] / count as f32])
+            };
+
+            // pairs at least 135 degrees apart with the region in between
+            let span = (0..SECTORS)
+                .flat_map(|a| (3..=5).map(move |d| (a, a + d)))
+                .filter_map(|(a, b)| {
+                    let (p, q) = (anchor(a)?, anchor(b)?);
+                    let middle = [(p[0] + q[0]) / 2.0, (p[1] + q[1]) / 2.0];
+                    if !unsupported.cells[unsupported.index(middle)?] {
+                        return None;
+                    }
+                    Some((p[0] - q[0]).hypot(p[1] - q[1]))
+                })
+                .reduce(f32::min)?;
+
+            Some(Bridge {
+                z,
+                area: region.len() as f32 * cell_area,
+                span,
+            })
+        })
+        .collect()
+}
+
+fn centroid(points: &[[f32; 2]]) -> [f32; 2] {
+    let n = points.len().max(1) as f32;
+    let (x, y) = points
+        .iter()
+        .fold((0.0, 0.0), |acc, p| (acc.0 + p[0], acc.1 + p[1]));
+    [x / n, y / n]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::slice::Grid;
+
+    /// cells whose centres lie within any of the rectangles `[x0, y0, x1, y1]`
+    fn mask(grid: &Grid, rectangles: &[[f32; 4]]) -> Mask {
+        let mut mask = Mask::empty(grid);
+        for i in 0..mask.cells.len() {
+            let [x, y] = mask.position(i);
+            mask.cells[i] = rectangles
+                .iter()
+                .any(|r| (r[0]..r[2]).contains(&x) && (r[1]..r[3]).contains(&y));
+        }
+        mask
+    }
+
+    #[test]
+    fn slab_on_two_pillars() {
+        let grid = Grid::covering([0.0, 0.0], [30.0, 10.0], 0.1);
+        let pillars = mask(&grid, &[[0.0, 0.0, 5.0, 10.0], [25.0, 0.0, 30.0, 10.0]]);
+        let slab = mask(&grid, &[[0.0, 0.0, 30.0, 10.0]]);
+
+        let found = bridges(&pillars, &slab, 4.2, 0.4);
+        assert_eq!(found.len(), 1);
+        let bridge = found[0];
+        assert_eq!(bridge.z, 4.2);
+        // the pillars hold the slab for half a line width past their edges
+        assert!((bridge.span - 19.7).abs() < 0.2, "span {}", bridge.span);
+        assert!(
+            (bridge.area - 196.0).abs() < 196.0 * 0.02,
+            "area {}",
+            bridge.area
+        );
+    }
+
+    #[test]
+    fn overhang_is_no_bridge() {
+        let grid = Grid::covering([0.0, 0.0], [15.0, 10.0], 0.1);
+        let pillar = mask(&grid, &[[0.0, 0.0, 5.0, 10.0]]);
+        let slab = mask(&grid, &[[0.0, 0.0, 15.0, 10.0]]);
+        assert!(bridges(&pillar, &slab, 4.2, 0.4).is_empty());
+
+        // fully supported
+        assert!(bridges(&slab, &slab, 4.2, 0.4).is_empty());
+    }
+}
@@ -0,0 +1,493 @@
+// Bounding volume hierarchy
+//
+// Triangles are split at the median of their centroids along the longest axis until
+// at most `LEAF_SIZE` remain. Nodes are stored depth first: the left child directly
+// follows its parent and the right child sits `skip` nodes further, so subtrees built
+// on different threads can simply be concatenated. The hierarchy borrows the
+// triangles, one build can serve any number of queries and analyses.
+
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use crate::model::Triangle;
+
+const LEAF_SIZE: usize = 4;
+/// below this many triangles a subtree is built on the current thread
+const PARALLEL_THRESHOLD: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+        }
+    }
+
+    pub fn of(triangle: &Triangle) -> Self {
+        let mut aabb = Self::empty();
+        for v in triangle.vertices() {
+            aabb = aabb.grow(*v);
+        }
+        aabb
+    }
+
+    pub fn grow(self, point: [f32; 3]) -> Self {
+        Self {
+            min: std::array::from_fn(|axis| self.min[axis].min(point[axis])),
+            max: std::array::from_fn(|axis| self.max[axis].max(point[axis])),
+        }
+    }
+
+    pub fn union(self, other: Aabb) -> Self {
+        self.grow(other.min).grow(other.max)
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    pub fn centre(&self) -> [f32; 3] {
+        std::array::from_fn(|axis| (self.min[axis] + self.max[axis]) / 2.0)
+    }
+
+    /// squared distance from `point` to the box, 0 inside
+    fn distance_squared(&self, point: [f32; 3]) -> f32 {
+        (0..3)
+            .map(|axis| {
+                let d = (self.min[axis] - point[axis]).max(point[axis] - self.max[axis]);
+                d.max(0.0).powi(2)
+            })
+            .sum()
+    }
+
+    /// entry distance of the ray into the box, if it enters before `max_distance`
+    fn entry(&self, ray: &Ray, inverse: [f32; 3], max_distance: f32) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, max_distance);
+        for (axis, inverse) in inverse.into_iter().enumerate() {
+            let t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            // NaN from 0 * inf means the ray runs inside the slab plane
+            let (t0, t1) = (t0.min(t1), t0.max(t1));
+            near = if t0.is_nan() { near } else { near.max(t0) };
+            far = if t1.is_nan() { far } else { far.min(t1) };
+        }
+        (near <= far).then_some(near)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: [f32; 3],
+    /// need not be normalised, distances are in multiples of its length
+    pub direction: [f32; 3],
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> [f32; 3] {
+        std::array::from_fn(|axis| self.origin[axis] + self.direction[axis] * distance)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    /// index into the triangles the hierarchy was built from
+    pub triangle: usize,
+    pub distance: f32,
+    /// the triangle faces along the ray, i.e. the ray leaves the solid here
+    pub backface: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Nearest {
+    pub triangle: usize,
+    pub point: [f32; 3],
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    /// first triangle of a leaf in `Bvh::order`
+    start: u32,
+    /// triangles in a leaf, 0 for inner nodes
+    count: u32,
+    /// offset of the right child of an inner node
+    skip: u32,
+}
+
+pub struct Bvh<'a> {
+    triangles: &'a [Triangle],
+    nodes: Vec<Node>,
+    order: Vec<u32>,
+}
+
+impl<'a> Bvh<'a> {
+    pub fn new(triangles: &'a [Triangle]) -> Self {
+        let mut items: Vec<(u32, Aabb)> = triangles
+            .par_iter()
+            .enumerate()
+            .map(|(i, triangle)| (i as u32, Aabb::of(triangle)))
+            .collect();
+
+        let nodes = if items.is_empty() {
+            Vec::new()
+        } else {
+            build(&mut items, 0)
+        };
+
+        Self {
+            triangles,
+            nodes,
+            order: items.into_iter().map(|(i, _)| i).collect(),
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or_else(Aabb::empty, |node| node.bounds)
+    }
+
+    /// closest hit along the ray within `max_distance`
+    pub fn ray(&self, ray: &Ray, max_distance: f32) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        self.traverse_ray(ray, max_distance, |hit, limit| {
+            if hit.distance < *limit {
+                *limit = hit.distance;
+                closest = Some(hit);
+            }
+        });
+        closest
+    }
+
+    /// every hit along the ray within `max_distance`, nearest first
+    pub fn hits(&self, ray: &Ray, max_distance: f32) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        self.traverse_ray(ray, max_distance, |hit, _| hits.push(hit));
+        hits.sort_unstable_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    fn traverse_ray(&self, ray: &Ray, max_distance: f32, mut visit: impl FnMut(Hit, &mut f32)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse = ray.direction.map(|d| 1.0 / d);
+        let mut limit = max_distance;
+        let mut stack = vec![0usize];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.entry(ray, inverse, limit).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(index + node.skip as usize);
+                stack.push(index + 1);
+                continue;
+            }
+            for &triangle in self.leaf(node) {
+                if let Some((distance, backface)) =
+                    intersect(&self.triangles[triangle as usize], ray)
+                    && distance <= limit
+                {
+                    let hit = Hit {
+                        triangle: triangle as usize,
+                        distance,
+                        backface,
+                    };
+                    visit(hit, &mut limit);
+                }
+            }
+        }
+    }
+
+    /// triangles whose bounding boxes overlap `aabb`
+    pub fn intersecting(&self, aabb: &Aabb) -> Vec<usize> {
+        let mut found = Vec::new();
+        if self.nodes.is_empty() {
+            return found;
+        }
+        let mut stack = vec![0usize];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.overlaps(aabb) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(index + node.skip as usize);
+                stack.push(index + 1);
+                continue;
+            }
+            found.extend(
+                self.leaf(node)
+                    .iter()
+                    .filter(|&&t| Aabb::of(&self.triangles[t as usize]).overlaps(aabb))
+                    .map(|&t| t as usize),
+            );
+        }
+
+        found
+    }
+
+    /// closest point on the surface to `point`
+    pub fn nearest(&self, point: [f32; 3]) -> Option<Nearest> {
+        self.nearest_within(point, f32::INFINITY)
+    }
+
+    /// closest point on the surface to `point`, if closer than `max_distance`
+    pub fn nearest_within(&self, point: [f32; 3], max_distance: f32) -> Option<Nearest> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut best: Option<Nearest> = None;
+        let mut best_squared = max_distance * max_distance;
+        let mut stack = vec![0usize];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.distance_squared(point) >= best_squared {
+                continue;
+            }
+            if node.count == 0 {
+                // visit the closer child first so the bound tightens sooner
+                let (left, right) = (index + 1, index + node.skip as usize);
+                let left_distance = self.nodes[left].bounds.distance_squared(point);
+                let right_distance = self.nodes[right].bounds.distance_squared(point);
+                if left_distance < right_distance {
+                    stack.push(right);
+                    stack.push(left);
+                } else {
+                    stack.push(left);
+                    stack.push(right);
+                }
+                continue;
+            }
+            for &triangle in self.leaf(node) {
+                let closest = closest_point(&self.triangles[triangle as usize], point);
+                let squared = (Vector3::from(closest) - Vector3::from(point)).norm_squared();
+                if squared < best_squared {
+                    best_squared = squared;
+                    best = Some(Nearest {
+                        triangle: triangle as usize,
+                        point: closest,
+                        distance: squared.sqrt(),
+                    });
+                }
+            }
+        }
+
+        best
+    }
+
+    fn leaf(&self, node: &Node) -> &[u32] {
+        &self.order[node.start as usize..(node.start + node.count) as usize]
+    }
+}
+
+/// Build the subtree over `items`, which start at `offset` in the final order.
+fn build(items: &mut [(u32, Aabb)], offset: usize) -> Vec<Node> {
+    let bounds = items
+        .iter()
+        .fold(Aabb::empty(), |bounds, (_, aabb)| bounds.union(*aabb));
+
+    if items.len() <= LEAF_SIZE {
+        return vec![Node {
+            bounds,
+            start: offset as u32,
+            count: items.len() as u32,
+            skip: 0,
+        }];
+    }
+
+    let centres = items.iter().fold(Aabb::empty(), |centres, (_, aabb)| {
+        centres.grow(aabb.centre())
+    });
+    let axis = (0..3)
+        .max_by(|&a, &b| {
+            (centres.max[a] - centres.min[a]).total_cmp(&(centres.max[b] - centres.min[b]))
+        })
+        .unwrap_or(0);
+
+    let middle = items.len() / 2;
+    items.select_nth_unstable_by(middle, |a, b| {
+        a.1.centre()[axis].total_cmp(&b.1.centre()[axis])
+    });
+    let (left, right) = items.split_at_mut(middle);
+
+    let (left, right) = if left.len() + right.len() > PARALLEL_THRESHOLD {
+        rayon::join(|| build(left, offset), || build(right, offset + middle))
+    } else {
+        (build(left, offset), build(right, offset + middle))
+    };
+
+    let mut nodes = Vec::with_capacity(1 + left.len() + right.len());
+    nodes.push(Node {
+        bounds,
+        start: 0,
+        count: 0,
+        skip: 1 + left.len() as u32,
+    });
+    nodes.extend(left);
+    nodes.extend(right);
+    nodes
+}
+
+/// Möller–Trumbore, returns the distance along the ray and whether the front of the
+/// triangle faces away from it
+fn intersect(triangle: &Triangle, ray: &Ray) -> Option<(f32, bool)> {
+    let [a, b, c] = triangle.vertices().map(Vector3::from);
+    let (origin, direction) = (Vector3::from(ray.origin), Vector3::from(ray.direction));
+
+    let (ab, ac) = (b - a, c - a);
+    let p = direction.cross(&ac);
+    let determinant = ab.dot(&p);
+    if determinant.abs() < f32::EPSILON * ab.norm() * ac.norm() * direction.norm() {
+        return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let s = origin - a;
+    let u = s.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&ab);
+    let v = direction.dot(&q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = ac.dot(&q) * inverse;
+    (distance >= 0.0).then_some((distance, determinant < 0.0))
+}
+
+/// closest point on a triangle, from Ericson's Real-Time Collision Detection
+fn closest_point(triangle: &Triangle, point: [f32; 3]) -> [f32; 3] {
+    let [a, b, c] = triangle.vertices().map(Vector3::from);
+    let p = Vector3::from(point);
+
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (ab.dot(&ap), ac.dot(&ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a.into();
+    }
+
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(&bp), ac.dot(&bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b.into();
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return (a + ab * (d1 / (d1 - d3))).into();
+    }
+
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(&cp), ac.dot(&cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c.into();
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return (a + ac * (d2 / (d2 - d6))).into();
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return (b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)))).into();
+    }
+
+    let denominator = 1.0 / (va + vb + vc);
+    (a + ab * (vb * denominator) + ac * (vc * denominator)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::test_support::cube;
+
+    /// `count` unit cubes along X, two millimetres apart; cube `k` is triangles
+    /// `12 * k..12 * (k + 1)`
+    fn cubes(count: usize) -> Vec<Triangle> {
+        (0..count)
+            .flat_map(|k| {
+                let x = 2.0 * k as f32;
+                cube([x, 0.0, 0.0], [x + 1.0, 1.0, 1.0])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rays_enter_and_leave() {
+        let triangles = cubes(10);
+        let bvh = Bvh::new(&triangles);
+
+        // through the fourth cube, which spans 6 to 7 along X
+        let ray = Ray {
+            origin: [6.3, -5.0, 0.6],
+            direction: [0.0, 1.0, 0.0],
+        };
+        let hit = bvh.ray(&ray, f32::INFINITY).unwrap();
+        assert!((hit.distance - 5.0).abs() < 1e-5);
+        assert!(!hit.backface);
+        assert!((36..48).contains(&hit.triangle));
+
+        let hits = bvh.hits(&ray, f32::INFINITY);
+        assert_eq!(hits.len(), 2);
+        assert!((hits[1].distance - 6.0).abs() < 1e-5);
+        assert!(hits[1].backface);
+        assert!(bvh.ray(&ray, 4.0).is_none());
+
+        // between two cubes
+        let gap = Ray {
+            origin: [7.5, -5.0, 0.6],
+            ..ray
+        };
+        assert!(bvh.ray(&gap, f32::INFINITY).is_none());
+
+        // from inside the first surface is left, distances count the direction length
+        let inside = Ray {
+            origin: [6.3, 0.4, 0.6],
+            direction: [0.0, 0.0, 2.0],
+        };
+        let hit = bvh.ray(&inside, f32::INFINITY).unwrap();
+        assert!(hit.backface);
+        assert!((hit.distance - 0.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn boxes_and_nearest_points() {
+        let triangles = cubes(10);
+        let bvh = Bvh::new(&triangles);
+        assert_eq!(bvh.bounds().min, [0.0, 0.0, 0.0]);
+        assert_eq!(bvh.bounds().max, [19.0, 1.0, 1.0]);
+
+        // the far side at x = 7 stays outside the box
+        let found = bvh.intersecting(&Aabb {
+            min: [5.5, -1.0, -1.0],
+            max: [6.2, 2.0, 2.0],
+        });
+        assert_eq!(found.len(), 10);
+        assert!(found.iter().all(|t| (36..48).contains(t)));
+
+        let above = bvh.nearest([6.5, 0.5, 3.0]).unwrap();
+        assert!((above.distance - 2.0).abs() < 1e-5);
+        assert!((above.point[2] - 1.0).abs() < 1e-5);
+        assert!((36..48).contains(&above.triangle));
+        assert!(bvh.nearest_within([6.5, 0.5, 3.0], 1.5).is_none());
+
+        let between = bvh.nearest_within([7.4, 0.5, 0.5], 1.0).unwrap();
+        assert!((between.distance - 0.4).abs() < 1e-5);
+        assert!((36..48).contains(&between.triangle));
+    }
+}
@@ -0,0 +1,224 @@
+// Trapped cavities
+//
+// A void that is open to the outside, even through a small drain hole, makes its
+// inner surface part of the same connected shell as the outer one. Sealed voids
+// leave a shell of their own inside the part instead. Every shell is therefore
+// tested for how many other shells enclose it, by counting ray crossings against
+// each of them: a shell inside an odd number of others bounds a cavity, one inside
+// an even number is solid again (a part floating in a cavity). The cavity volume
+// excludes such floating parts. Only closed shells, every edge shared by an even
+// number of faces, enclose anything; open ones are ignored.
+//
+// The wall of a void faces into it, so only shells with a negative signed volume
+// can bound a cavity; separate bodies of a multi-body export overlapping each other
+// face outwards and stay solid. A shell counts as enclosed only when points spread
+// over its whole surface are all inside, not just one vertex that pokes into a
+// neighbouring body.
+
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::{
+    calculate::bvh::{Aabb, Bvh, Ray},
+    error::AppError,
+    model::{Triangle, mesh::IndexedMesh},
+};
+
+/// most separate shells tested against each other
+pub const MAX_SHELLS: usize = 10_000;
+
+/// skewed directions, so rays rarely run exactly along edges of CAD models
+const DIRECTIONS: [[f32; 3]; 3] = [
+    [0.5773, 0.5774, 0.5773],
+    [-0.6234, 0.3455, 0.7014],
+    [0.2817, -0.8912, -0.3556],
+];
+
+/// points of every shell tested for being enclosed
+const PROBES: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cavity {
+    /// enclosed volume (mm³)
+    pub volume: f32,
+    /// bounding box of the cavity (mm)
+    pub bounds: Aabb,
+}
+
+pub fn cavities(triangles: &[Triangle]) -> Result<Vec<Cavity>, AppError> {
+    let mesh = IndexedMesh::from_triangles(triangles);
+    let (count, shells) = mesh.shells();
+    if count > MAX_SHELLS {
+        return Err(AppError::bad_request(format!(
+            "too many separate shells to detect cavities, the limit is {}",
+            MAX_SHELLS
+        )));
+    }
+    let closed = closed_shells(&mesh, &shells, count);
+    if closed.iter().filter(|&&closed| closed).count() < 2 {
+        return Ok(Vec::new());
+    }
+
+    let mut volumes = vec![0.0f32; count];
+    let mut bounds = vec![Aabb::empty(); count];
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); count];
+    for (i, (triangle, &shell)) in triangles.iter().zip(shells.iter()).enumerate() {
+        let shell = shell as usize;
+        volumes[shell] += triangle.signed_volume();
+        bounds[shell] = bounds[shell].union(Aabb::of(triangle));
+        members[shell].push(i);
+    }
+
+    // shells enclosing every shell, decided by the majority of the rays from each
+    // probe and agreed on by all probes
+    let bvh = Bvh::new(triangles);
+    let enclosing: Vec<Vec<usize>> = members
+        .par_iter()
+        .enumerate()
+        .map(|(shell, members)| {
+            if !closed[shell] || members.is_empty() {
+                return Vec::new();
+            }
+            let mut probes: Vec<usize> = (0..PROBES)
+                .map(|k| members[k * members.len() / PROBES])
+                .collect();
+            probes.dedup();
+
+            let mut enclosing: Option<Vec<usize>> = None;
+            for &probe in &probes {
+                let inside =
+                    enclosing_at(&bvh, &shells, &closed, shell, centroid(&triangles[probe]));
+                enclosing = Some(match enclosing {
+                    None => inside,
+                    Some(found) => found
+                        .into_iter()
+                        .filter(|other| inside.contains(other))
+                        .collect(),
+                });
+            }
+            enclosing.unwrap_or_default()
+        })
+        .collect();
+
+    Ok((0..count)
+        .filter(|&shell| closed[shell] && volumes[shell] < 0.0 && enclosing[shell].len() % 2 == 1)
+        .map(|shell| {
+            // solid parts floating directly inside this cavity
+            let depth = enclosing[shell].len();
+            let floating: f32 = (0..count)
+                .filter(|&inner| {
+                    enclosing[inner].len() == depth + 1 && enclosing[inner].contains(&shell)
+                })
+                .map(|inner| volumes[inner].abs())
+                .sum();
+
+            Cavity {
+                volume: (volumes[shell].abs() - floating).max(0.0),
+                bounds: bounds[shell],
+            }
+        })
+        .collect())
+}
+
+/// closed shells other than `shell` that `origin` lies inside
+fn enclosing_at(
+    bvh: &Bvh,
+    shells: &[u32],
+    closed: &[bool],
+    shell: usize,
+    origin: [f32; 3],
+) -> Vec<usize> {
+    let mut votes: HashMap<usize, usize> = HashMap::new();
+    for direction in DIRECTIONS {
+        let mut crossings: HashMap<usize, usize> = HashMap::new();
+        for hit in bvh.hits(&Ray { origin, direction }, f32::INFINITY) {
+            let other = shells[hit.triangle] as usize;
+            if other != shell && closed[other] {
+                *crossings.entry(other).or_default() += 1;
+            }
+        }
+        for (other, crossings) in crossings {
+            if crossings % 2 == 1 {
+                *votes.entry(other).or_default() += 1;
+            }
+        }
+    }
+    votes
+        .into_iter()
+        .filter(|&(_, votes)| votes * 2 > DIRECTIONS.len())
+        .map(|(other, _)| other)
+        .collect()
+}
+
+fn centroid(triangle: &Triangle) -> [f32; 3] {
+    let [a, b, c] = *triangle.vertices();
+    std::array::from_fn(|axis| (a[axis] + b[axis] + c[axis]) / 3.0)
+}
+
+/// whether every shell is closed, each of its edges shared by an even number of faces
+fn closed_shells(mesh: &IndexedMesh, shells: &[u32], count: usize) -> Vec<bool> {
+    let mut edges: HashMap<(u32, u32), (u32, usize)> = HashMap::new();
+    for (face, &shell) in mesh.faces.iter().zip(shells.iter()) {
+        for k in 0..3 {
+            let (a, b) = (face[k], face[(k + 1) % 3]);
+            edges.entry((a.min(b), a.max(b))).or_insert((shell, 0)).1 += 1;
+        }
+    }
+
+    let mut closed = vec![true; count];
+    for (shell, faces) in edges.into_values() {
+        if faces % 2 == 1 {
+            closed[shell as usize] = false;
+        }
+    }
+    closed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::test_support::{cube, flipped};
+
+    #[test]
+    fn sealed_void() {
+        let mut triangles = cube([0.0; 3], [10.0; 3]);
+        triangles.extend(flipped(cube([2.0; 3], [8.0; 3])));
+        let found = cavities(&triangles).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!((found[0].volume - 216.0).abs() < 1e-2);
+        assert_eq!(found[0].bounds.min, [2.0; 3]);
+        assert_eq!(found[0].bounds.max, [8.0; 3]);
+
+        // a part floating inside the void takes up some of it
+        triangles.extend(cube([4.0; 3], [6.0; 3]));
+        let found = cavities(&triangles).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!((found[0].volume - 208.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn separate_and_open_shells_are_no_voids() {
+        let mut triangles = cube([0.0; 3], [10.0; 3]);
+        triangles.extend(cube([20.0, 0.0, 0.0], [24.0, 4.0, 4.0]));
+        assert!(cavities(&triangles).unwrap().is_empty());
+
+        // an inner wall missing a face is open to the rest of the part
+        let mut triangles = cube([0.0; 3], [10.0; 3]);
+        triangles.extend(flipped(cube([2.0; 3], [8.0; 3])).into_iter().skip(2));
+        assert!(cavities(&triangles).unwrap().is_empty());
+    }
+
+    #[test]
+    fn overlapping_bodies_are_no_voids() {
+        // a body inside another one, facing outwards, is a second solid
+        let mut triangles = cube([0.0; 3], [10.0; 3]);
+        triangles.extend(cube([2.0; 3], [8.0; 3]));
+        assert!(cavities(&triangles).unwrap().is_empty());
+
+        // two bodies cutting into each other, the first vertex of either inside the other
+        let mut triangles = cube([0.0; 3], [10.0; 3]);
+        triangles.extend(cube([5.0; 3], [15.0; 3]));
+        assert!(cavities(&triangles).unwrap().is_empty());
+    }
+}
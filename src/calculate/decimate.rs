@@ -0,0 +1,533 @@
+// Quadric error metric decimation (Garland & Heckbert)
+//
+// Every vertex accumulates the quadric of the planes of its faces, an edge is
+// collapsed into the point minimising the summed quadric and the cheapest edge is
+// always collapsed first. Stale heap entries are skipped using per-vertex
+// versions, and collapses that would flip a face or break the link condition are
+// rejected.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use nalgebra::{Matrix3, Vector3};
+use rayon::prelude::*;
+
+use crate::model::mesh::IndexedMesh;
+
+/// weight of the planes added along open edges, keeps holes from shrinking
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Target {
+    /// stop once the mesh has at most this many triangles
+    pub triangles: usize,
+    /// stop once the next collapse would move the surface further than this (mm)
+    pub max_error: Option<f64>,
+}
+
+/// symmetric 4x4 matrix, upper triangle stored row by row
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(n: Vector3<f64>, d: f64, weight: f64) -> Self {
+        let (a, b, c) = (n.x, n.y, n.z);
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|q| q * weight),
+        )
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let mut q = self.0;
+        q.iter_mut().zip(other.0.iter()).for_each(|(a, b)| *a += b);
+        Self(q)
+    }
+
+    fn error(&self, v: &Vector3<f64>) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (v.x, v.y, v.z);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+
+    fn optimal(&self) -> Option<Vector3<f64>> {
+        let q = &self.0;
+        let a = Matrix3::new(q[0], q[1], q[2], q[1], q[4], q[5], q[2], q[5], q[7]);
+        if a.determinant().abs() < 1e-12 {
+            return None;
+        }
+        a.try_inverse()
+            .map(|inv| inv * -Vector3::new(q[3], q[6], q[8]))
+    }
+}
+
+struct Candidate {
+    cost: f64,
+    a: u32,
+    b: u32,
+    versions: (u32, u32),
+    position: Vector3<f64>,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    // reversed so the max-heap pops the cheapest collapse
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Decimator {
+    positions: Vec<Vector3<f64>>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    removed: Vec<bool>,
+    faces: Vec<[u32; 3]>,
+    face_removed: Vec<bool>,
+    vertex_faces: Vec<Vec<u32>>,
+}
+
+impl Decimator {
+    fn new(mesh: &IndexedMesh) -> Self {
+        let positions: Vec<Vector3<f64>> = mesh
+            .vertices
+            .iter()
+            .map(|v| Vector3::new(v[0] as f64, v[1] as f64, v[2] as f64))
+            .collect();
+
+        let mut vertex_faces = vec![Vec::new(); positions.len()];
+        for (index, face) in mesh.faces.iter().enumerate() {
+            for &v in face {
+                vertex_faces[v as usize].push(index as u32);
+            }
+        }
+
+        let face_planes: Vec<Option<(Vector3<f64>, f64)>> = mesh
+            .faces
+            .par_iter()
+            .map(|face| {
+                let [a, b, c] = face.map(|i| positions[i as usize]);
+                let normal = (b - a).cross(&(c - a));
+                let area = normal.norm();
+                (area > 0.0).then(|| {
+                    let n = normal / area;
+                    (n, -n.dot(&a))
+                })
+            })
+            .collect();
+
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        for (face, plane) in mesh.faces.iter().zip(face_planes.iter()) {
+            if let Some((n, d)) = plane {
+                let q = Quadric::plane(*n, *d, 1.0);
+                for &v in face {
+                    quadrics[v as usize] = quadrics[v as usize].add(&q);
+                }
+            }
+        }
+
+        // edges used by a single face are open, pin them with perpendicular planes
+        let mut edge_use: HashMap<(u32, u32), (u32, usize)> = HashMap::new();
+        for (index, face) in mesh.faces.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (face[k], face[(k + 1) % 3]);
+                let key = (a.min(b), a.max(b));
+                edge_use.entry(key).or_insert((0, index)).0 += 1;
+            }
+        }
+        for ((a, b), (count, face)) in edge_use {
+            if count != 1 {
+                continue;
+            }
+            let Some((n, _)) = face_planes[face] else {
+                continue;
+            };
+            let (pa, pb) = (positions[a as usize], positions[b as usize]);
+            let edge = pb - pa;
+            let perpendicular = edge.cross(&n);
+            let length = perpendicular.norm();
+            if length == 0.0 {
+                continue;
+            }
+            let perpendicular = perpendicular / length;
+            let q = Quadric::plane(perpendicular, -perpendicular.dot(&pa), BOUNDARY_WEIGHT);
+            quadrics[a as usize] = quadrics[a as usize].add(&q);
+            quadrics[b as usize] = quadrics[b as usize].add(&q);
+        }
+
+        Self {
+            versions: vec![0; positions.len()],
+            removed: vec![false; positions.len()],
+            face_removed: vec![false; mesh.faces.len()],
+            faces: mesh.faces.clone(),
+            positions,
+            quadrics,
+            vertex_faces,
+        }
+    }
+
+    fn candidate(&self, a: u32, b: u32) -> Candidate {
+        let q = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+
+        let (position, cost) = q
+            .optimal()
+            .map(|p| (p, q.error(&p)))
+            .into_iter()
+            .chain([pa, pb, (pa + pb) / 2.0].map(|p| (p, q.error(&p))))
+            .min_by(|x, y| x.1.total_cmp(&y.1))
+            .unwrap_or((pa, 0.0));
+
+        Candidate {
+            cost: cost.max(0.0),
+            a,
+            b,
+            versions: (self.versions[a as usize], self.versions[b as usize]),
+            position,
+        }
+    }
+
+    fn neighbours(&self, v: u32) -> Vec<u32> {
+        let mut neighbours: Vec<u32> = self.vertex_faces[v as usize]
+            .iter()
+            .filter(|&&f| !self.face_removed[f as usize])
+            .flat_map(|&f| self.faces[f as usize])
+            .filter(|&n| n != v)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    /// Link condition: the only neighbours `a` and `b` share are the corners opposite
+    /// the edge, and no face of `b` turns into a copy of a face of `a`. Collapsing
+    /// anything else pinches thin parts into non-manifold edges and duplicate faces.
+    fn links(&self, a: u32, b: u32) -> bool {
+        let live = |v: u32| {
+            self.vertex_faces[v as usize]
+                .iter()
+                .filter(|&&f| !self.face_removed[f as usize])
+                .map(|&f| self.faces[f as usize])
+        };
+
+        let mut opposite: Vec<u32> = live(a)
+            .filter(|face| face.contains(&b))
+            .flat_map(|face| face.into_iter().filter(|&v| v != a && v != b))
+            .collect();
+        opposite.sort_unstable();
+        opposite.dedup();
+        let neighbours = self.neighbours(b);
+        let shared: Vec<u32> = self
+            .neighbours(a)
+            .into_iter()
+            .filter(|n| *n != b && neighbours.binary_search(n).is_ok())
+            .collect();
+        if shared != opposite {
+            return false;
+        }
+
+        let corners = |mut face: [u32; 3]| {
+            face.sort_unstable();
+            face
+        };
+        let faces_a: Vec<[u32; 3]> = live(a)
+            .filter(|face| !face.contains(&b))
+            .map(corners)
+            .collect();
+        !live(b)
+            .filter(|face| !face.contains(&a))
+            .any(|face| faces_a.contains(&corners(face.map(|v| if v == b { a } else { v }))))
+    }
+
+    /// true if moving `v` to `position` turns any of its faces (other than the
+    /// ones shared with `other`) upside down
+    fn flips(&self, v: u32, other: u32, position: &Vector3<f64>) -> bool {
+        self.vertex_faces[v as usize]
+            .iter()
+            .filter(|&&f| !self.face_removed[f as usize])
+            .map(|&f| self.faces[f as usize])
+            .filter(|face| !face.contains(&other))
+            .any(|face| {
+                let before = face.map(|i| self.positions[i as usize]);
+                let after = face.map(|i| {
+                    if i == v {
+                        *position
+                    } else {
+                        self.positions[i as usize]
+                    }
+                });
+                let n0 = (before[1] - before[0]).cross(&(before[2] - before[0]));
+                let n1 = (after[1] - after[0]).cross(&(after[2] - after[0]));
+                n0.dot(&n1) <= 0.0
+            })
+    }
+
+    fn collapse(&mut self, candidate: &Candidate) -> usize {
+        let (a, b) = (candidate.a, candidate.b);
+        let mut dropped = 0;
+
+        let faces_b = std::mem::take(&mut self.vertex_faces[b as usize]);
+        for f in faces_b {
+            if self.face_removed[f as usize] {
+                continue;
+            }
+            let face = &mut self.faces[f as usize];
+            if face.contains(&a) {
+                self.face_removed[f as usize] = true;
+                dropped += 1;
+            } else {
+                face.iter_mut().filter(|i| **i == b).for_each(|i| *i = a);
+                self.vertex_faces[a as usize].push(f);
+            }
+        }
+
+        let face_removed = &self.face_removed;
+        self.vertex_faces[a as usize].retain(|&f| !face_removed[f as usize]);
+
+        self.positions[a as usize] = candidate.position;
+        self.quadrics[a as usize] = self.quadrics[a as usize].add(&self.quadrics[b as usize]);
+        self.removed[b as usize] = true;
+        self.versions[a as usize] += 1;
+        self.versions[b as usize] += 1;
+
+        dropped
+    }
+}
+
+/// Simplify the mesh until it reaches the target triangle count or error bound.
+pub fn decimate(mesh: &IndexedMesh, target: &Target) -> IndexedMesh {
+    let mut decimator = Decimator::new(mesh);
+    let mut remaining = mesh.faces.len();
+    let max_cost = target.max_error.map(|e| e * e);
+
+    let mut edges: Vec<(u32, u32)> = mesh
+        .faces
+        .iter()
+        .flat_map(|f| [(f[0], f[1]), (f[1], f[2]), (f[2], f[0])])
+        .map(|(a, b)| (a.min(b), a.max(b)))
+        .collect();
+    edges.par_sort_unstable();
+    edges.dedup();
+
+    let mut heap: BinaryHeap<Candidate> = edges
+        .par_iter()
+        .map(|&(a, b)| decimator.candidate(a, b))
+        .collect::<Vec<_>>()
+        .into();
+
+    while remaining > target.triangles {
+        let Some(candidate) = heap.pop() else {
+            break;
+        };
+        let (a, b) = (candidate.a, candidate.b);
+        if decimator.removed[a as usize]
+            || decimator.removed[b as usize]
+            || candidate.versions
+                != (
+                    decimator.versions[a as usize],
+                    decimator.versions[b as usize],
+                )
+        {
+            continue;
+        }
+        if let Some(max_cost) = max_cost
+            && candidate.cost > max_cost
+        {
+            break;
+        }
+        if !decimator.links(a, b)
+            || decimator.flips(a, b, &candidate.position)
+            || decimator.flips(b, a, &candidate.position)
+        {
+            continue;
+        }
+
+        remaining -= decimator.collapse(&candidate);
+        for n in decimator.neighbours(a) {
+            heap.push(decimator.candidate(a.min(n), a.max(n)));
+        }
+    }
+
+    // compact the surviving faces and vertices
+    let mut remap = vec![u32::MAX; decimator.positions.len()];
+    let mut vertices = Vec::new();
+    let faces = decimator
+        .faces
+        .iter()
+        .zip(decimator.face_removed.iter())
+        .filter(|(_, removed)| !**removed)
+        .map(|(face, _)| {
+            face.map(|v| {
+                if remap[v as usize] == u32::MAX {
+                    let p = decimator.positions[v as usize];
+                    vertices.push([p.x as f32, p.y as f32, p.z as f32]);
+                    remap[v as usize] = (vertices.len() - 1) as u32;
+                }
+                remap[v as usize]
+            })
+        })
+        .collect();
+
+    IndexedMesh { vertices, faces }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{calculate, model::Triangle};
+
+    /// box from the origin to `size` with every side split into n by n rectangles
+    fn block(n: usize, size: [f32; 3]) -> IndexedMesh {
+        let [x, y, z] = size;
+        // corner, then two axes whose cross product points outwards
+        let sides = [
+            ([0.0, 0.0, 0.0], 1, 0),
+            ([0.0, 0.0, z], 0, 1),
+            ([0.0, 0.0, 0.0], 0, 2),
+            ([0.0, y, 0.0], 2, 0),
+            ([0.0, 0.0, 0.0], 2, 1),
+            ([x, 0.0, 0.0], 1, 2),
+        ];
+        let mut triangles = Vec::new();
+        for (origin, u, v) in sides {
+            let point = |i: usize, j: usize| {
+                let mut p: [f32; 3] = origin;
+                p[u] += i as f32 * size[u] / n as f32;
+                p[v] += j as f32 * size[v] / n as f32;
+                p
+            };
+            for i in 0..n {
+                for j in 0..n {
+                    let (a, b, c, d) = (
+                        point(i, j),
+                        point(i + 1, j),
+                        point(i + 1, j + 1),
+                        point(i, j + 1),
+                    );
+                    triangles.push(Triangle::new([a, b, c]));
+                    triangles.push(Triangle::new([a, c, d]));
+                }
+            }
+        }
+        IndexedMesh::from_triangles(&triangles)
+    }
+
+    /// every directed edge is used once and its reverse once, and no two faces
+    /// share all three corners
+    fn closed(mesh: &IndexedMesh) -> bool {
+        let corners: HashSet<[u32; 3]> = mesh
+            .faces
+            .iter()
+            .map(|face| {
+                let mut face = *face;
+                face.sort_unstable();
+                face
+            })
+            .collect();
+        if corners.len() != mesh.faces.len() || corners.iter().any(|f| f[0] == f[1] || f[1] == f[2])
+        {
+            return false;
+        }
+
+        let edges: Vec<(u32, u32)> = mesh
+            .faces
+            .iter()
+            .flat_map(|f| [(f[0], f[1]), (f[1], f[2]), (f[2], f[0])])
+            .collect();
+        let unique: HashSet<(u32, u32)> = edges.iter().copied().collect();
+        unique.len() == edges.len() && edges.iter().all(|&(a, b)| unique.contains(&(b, a)))
+    }
+
+    #[test]
+    fn decimated_cube_stays_closed() {
+        let mesh = block(4, [8.0; 3]);
+        assert_eq!(mesh.faces.len(), 192);
+        assert!(closed(&mesh));
+
+        let target = Target {
+            triangles: 40,
+            max_error: None,
+        };
+        let result = decimate(&mesh, &target);
+        assert!(result.faces.len() <= 40);
+        assert!(closed(&result));
+        let volume = calculate::volume(&result.to_triangles());
+        assert!((volume - 512.0).abs() < 1e-2, "volume {volume}");
+    }
+
+    #[test]
+    fn error_bound_keeps_the_corners() {
+        let target = Target {
+            triangles: 0,
+            max_error: Some(1e-6),
+        };
+        let result = decimate(&block(4, [8.0; 3]), &target);
+        assert!(result.faces.len() >= 12);
+        assert!(closed(&result));
+        let volume = calculate::volume(&result.to_triangles());
+        assert!((volume - 512.0).abs() < 1e-2, "volume {volume}");
+    }
+
+    #[test]
+    fn thin_parts_stay_manifold() {
+        let target = Target {
+            triangles: 0,
+            max_error: None,
+        };
+        let plate = block(6, [20.0, 20.0, 0.2]);
+        assert!(closed(&plate));
+        let result = decimate(&plate, &target);
+        assert!(result.faces.len() >= 4);
+        assert!(closed(&result));
+
+        let tetrahedron = IndexedMesh {
+            vertices: vec![
+                [0.0, 0.0, 0.0],
+                [10.0, 0.0, 0.0],
+                [0.0, 10.0, 0.0],
+                [0.0, 0.0, 10.0],
+            ],
+            faces: vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]],
+        };
+        let result = decimate(&tetrahedron, &target);
+        assert_eq!(result.faces.len(), 4);
+        assert!(closed(&result));
+    }
+}
@@ -0,0 +1,210 @@
+// FFF print estimate
+//
+// The model is sliced through the middle of every layer of the stack. Per layer the
+// perimeters are printed `walls` times along the contours, the top and bottom skins
+// are filled solid and the remaining area at the infill density. Time is the
+// extruded volume divided by the volumetric flow of the nozzle plus a fixed cost per
+// layer change, bridges are printed slower which is added on top.
+
+use std::collections::{BTreeSet, HashMap};
+
+use rayon::prelude::*;
+
+use crate::{
+    calculate::{
+        self,
+        adhesion::{self, Adhesion, LineItem},
+        bridges::{self, Bridge},
+        layers::{self, LayerHeights, Slab},
+        raster::Mask,
+        skin,
+        slice::{self, Grid},
+    },
+    error::AppError,
+    model,
+};
+
+/// seconds spent moving to the next layer
+const LAYER_CHANGE_TIME: f32 = 1.0;
+/// bridges are printed at this fraction of the print speed
+const BRIDGE_SPEED: f32 = 0.5;
+/// upper limit for the cells along either side of the layer masks
+const RASTER_SIZE: f32 = 1024.0;
+/// layers handled together, with the masks they need held in memory
+const MASK_CHUNK: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub layer_heights: LayerHeights,
+    /// extrusion width (mm)
+    pub line_width: f32,
+    pub walls: u32,
+    /// fraction of the interior filled, 0 to 1
+    pub infill: f32,
+    /// print speed (mm/s)
+    pub speed: f32,
+    /// thickness of the solid top and bottom surfaces (mm)
+    pub skin_thickness: f32,
+    pub adhesion: Option<Adhesion>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            layer_heights: LayerHeights::Fixed(0.2),
+            line_width: 0.4,
+            walls: 2,
+            infill: 0.2,
+            speed: 60.0,
+            skin_thickness: 0.8,
+            adhesion: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub layers: Vec<Slab>,
+    /// extruded material (mm³)
+    pub material: f32,
+    /// print time (s)
+    pub time: f32,
+    /// part of `material` printed as solid top and bottom skin (mm³)
+    pub skin: f32,
+    /// brim, skirt and raft, priced separately from the part itself
+    pub adhesion: Vec<LineItem>,
+    pub bridges: Vec<Bridge>,
+}
+
+pub fn estimate(triangles: &[model::Triangle], settings: &Settings) -> Result<Estimate, AppError> {
+    let (min, max) = calculate::bounds(triangles);
+    if triangles.is_empty() || max[2] <= min[2] {
+        return Ok(Estimate {
+            layers: Vec::new(),
+            material: 0.0,
+            time: 0.0,
+            skin: 0.0,
+            adhesion: Vec::new(),
+            bridges: Vec::new(),
+        });
+    }
+
+    let stack = layers::stack(triangles, min[2], max[2], &settings.layer_heights)?;
+    let heights: Vec<f32> = stack.iter().map(Slab::middle).collect();
+    let sections = slice::slice(triangles, &heights);
+
+    let cell = (settings.line_width / 2.0)
+        .max((max[0] - min[0]) / RASTER_SIZE)
+        .max((max[1] - min[1]) / RASTER_SIZE);
+    let grid = Grid::covering([min[0], min[1]], [max[0], max[1]], cell);
+
+    // The skin windows slide up the stack a chunk of layers at a time. Each chunk
+    // rasterises its own layers, the one below and those entering or leaving the
+    // window once; masks of the whole stack or even of whole windows would not fit in
+    // memory for tall models with thick skins.
+    let mut coverage = skin::Coverage::new(&grid);
+    let mut per_layer: Vec<(f32, f32, f32, Vec<Bridge>)> = Vec::with_capacity(stack.len());
+    for start in (0..stack.len()).step_by(MASK_CHUNK) {
+        let end = (start + MASK_CHUNK).min(stack.len());
+        let windows: Vec<skin::Window> = (start..end)
+            .map(|i| skin::window(&stack, i, settings.skin_thickness))
+            .collect();
+        let (counted, last) = (coverage.layers(), windows[windows.len() - 1].layers.clone());
+        let needed: BTreeSet<usize> = (start.saturating_sub(1)..end)
+            .chain(counted.start..last.start.min(counted.end))
+            .chain(counted.end..last.end)
+            .collect();
+        let masks: HashMap<usize, Mask> = needed
+            .into_par_iter()
+            .map(|j| (j, Mask::new(&sections[j], &grid)))
+            .collect();
+        let mask = |j: usize| &masks[&j];
+
+        let solids: Vec<f32> = windows
+            .into_iter()
+            .enumerate()
+            .map(|(k, window)| {
+                coverage.slide(window.layers, mask);
+                coverage.skin(mask(start + k), window.outside).area()
+            })
+            .collect();
+
+        per_layer.par_extend(solids.into_par_iter().enumerate().map(|(k, solid)| {
+            let i = start + k;
+            let (slab, layer) = (&stack[i], &sections[i]);
+            let current = mask(i);
+
+            let area = layer.area();
+            let perimeter = layer.perimeter();
+            let walls = (perimeter * settings.walls as f32 * settings.line_width).min(area);
+            let interior = area - walls;
+            let solid = solid.min(interior);
+            let volume = (walls + solid + (interior - solid) * settings.infill) * slab.height;
+            let flow = settings.line_width * slab.height * settings.speed;
+
+            let bridges = match i.checked_sub(1) {
+                Some(j) => bridges::bridges(mask(j), current, layer.z, settings.line_width),
+                None => Vec::new(),
+            };
+            // bridges are part of the bottom skin, only their lower speed is extra
+            let bridge_time: f32 = bridges
+                .iter()
+                .map(|bridge| bridge.area / (settings.line_width * settings.speed))
+                .sum::<f32>()
+                * (1.0 / BRIDGE_SPEED - 1.0);
+
+            (
+                volume,
+                volume / flow + bridge_time + LAYER_CHANGE_TIME,
+                solid * slab.height,
+                bridges,
+            )
+        }));
+    }
+
+    let material = per_layer.iter().map(|layer| layer.0).sum();
+    let time = per_layer.iter().map(|layer| layer.1).sum();
+    let skin = per_layer.iter().map(|layer| layer.2).sum();
+    let bridges = per_layer.into_iter().flat_map(|layer| layer.3).collect();
+
+    let adhesion = match (settings.adhesion.as_ref(), stack.first(), sections.first()) {
+        (Some(adhesion), Some(slab), Some(first)) => adhesion::adhesion(
+            first,
+            slab.height,
+            settings.line_width,
+            settings.speed,
+            adhesion,
+        ),
+        _ => Vec::new(),
+    };
+
+    Ok(Estimate {
+        layers: stack,
+        material,
+        time,
+        skin,
+        adhesion,
+        bridges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::test_support::cube;
+
+    #[test]
+    fn skin_of_a_cube() {
+        let estimate = estimate(&cube([0.0; 3], [20.0; 3]), &Settings::default()).unwrap();
+        assert_eq!(estimate.layers.len(), 100);
+        // four solid layers at either end, the walls taken off their area
+        let interior = 400.0 - 80.0 * 2.0 * 0.4;
+        let expected = 8.0 * 0.2 * interior;
+        assert!(
+            (estimate.skin - expected).abs() < expected * 0.01,
+            "{}",
+            estimate.skin
+        );
+        assert!(estimate.bridges.is_empty());
+    }
+}
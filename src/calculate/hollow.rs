@@ -0,0 +1,309 @@
+// SLA hollowing
+//
+// The part is sampled on a voxel grid with the distance of every interior sample to
+// the surface; whether a sample is inside follows from the crossings of a vertical
+// ray through its column. The inner wall is the surface where that distance equals
+// the wall thickness, extracted with marching tetrahedra so neighbouring cells always
+// agree on the shared faces and the result is closed. Every separate pocket gets a
+// drain hole drilled straight down from its lowest point to the outside.
+
+use std::f32::consts::PI;
+
+use rayon::prelude::*;
+
+use crate::{
+    calculate::{
+        self,
+        bvh::{Bvh, Ray},
+    },
+    model::{Triangle, mesh::IndexedMesh},
+};
+
+/// upper limit for the samples along the longest side of the model
+const RESOLUTION: f32 = 160.0;
+
+/// offset of the grid in cells, different per axis
+const JITTER: [f32; 3] = [0.9137, 0.8671, 0.9419];
+
+/// the cube split into six tetrahedra around its main diagonal
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 5, 1, 6],
+    [0, 1, 2, 6],
+    [0, 2, 3, 6],
+    [0, 3, 7, 6],
+    [0, 7, 4, 6],
+    [0, 4, 5, 6],
+];
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [1, 1, 0],
+    [0, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [1, 1, 1],
+    [0, 1, 1],
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// thickness of the remaining shell (mm)
+    pub wall_thickness: f32,
+    /// diameter of the drain holes (mm), `None` leaves the pockets sealed
+    pub drain_diameter: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DrainHole {
+    /// where the hole leaves the outer surface (mm)
+    pub position: [f32; 3],
+    /// length of the hole through the wall (mm)
+    pub depth: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hollowing {
+    /// inner wall, facing into the hollow
+    pub inner: IndexedMesh,
+    /// resin needed for the hollowed part (mm³)
+    pub resin: f32,
+    /// resin needed for the solid part (mm³)
+    pub solid: f32,
+    pub drain_holes: Vec<DrainHole>,
+}
+
+pub fn hollow(triangles: &[Triangle], settings: &Settings) -> Hollowing {
+    let solid = calculate::volume(triangles);
+    let mut result = Hollowing {
+        inner: IndexedMesh::default(),
+        resin: solid,
+        solid,
+        drain_holes: Vec::new(),
+    };
+    if triangles.is_empty() {
+        return result;
+    }
+
+    let (min, max) = calculate::bounds(triangles);
+    let extent = (0..3).map(|axis| max[axis] - min[axis]).fold(0.0, f32::max);
+    let cell = (settings.wall_thickness / 3.0).max(extent / RESOLUTION);
+    // a margin of one cell keeps the inner surface closed, the odd fractions keep the
+    // sample columns off the edges and diagonals of axis aligned models
+    let origin: [f32; 3] = std::array::from_fn(|axis| min[axis] - cell * JITTER[axis]);
+    let size: [usize; 3] =
+        std::array::from_fn(|axis| ((max[axis] - origin[axis]) / cell).ceil() as usize + 2);
+    let position = |i: [usize; 3]| -> [f32; 3] {
+        std::array::from_fn(|axis| origin[axis] + i[axis] as f32 * cell)
+    };
+
+    let bvh = Bvh::new(triangles);
+    let cap = settings.wall_thickness + 2.0 * cell;
+
+    // distance to the surface for interior samples, -1 outside, stored column major
+    let field: Vec<f32> = (0..size[0] * size[1])
+        .into_par_iter()
+        .flat_map_iter(|column| {
+            let (x, y) = (column % size[0], column / size[0]);
+            let bottom = position([x, y, 0]);
+            let hits = bvh.hits(
+                &Ray {
+                    origin: bottom,
+                    direction: [0.0, 0.0, 1.0],
+                },
+                f32::INFINITY,
+            );
+            let bvh = &bvh;
+            (0..size[2]).map(move |z| {
+                let p = position([x, y, z]);
+                let crossings = hits
+                    .iter()
+                    .filter(|hit| hit.distance < p[2] - bottom[2])
+                    .count();
+                if crossings % 2 == 0 {
+                    return -1.0;
+                }
+                bvh.nearest_within(p, cap)
+                    .map_or(cap, |nearest| nearest.distance)
+            })
+        })
+        .collect();
+    let value = |i: [usize; 3]| field[(i[1] * size[0] + i[0]) * size[2] + i[2]];
+
+    let iso = settings.wall_thickness;
+    let inner: Vec<Triangle> = (0..(size[0] - 1) * (size[1] - 1) * (size[2] - 1))
+        .into_par_iter()
+        .flat_map_iter(|index| {
+            let x = index % (size[0] - 1);
+            let y = index / (size[0] - 1) % (size[1] - 1);
+            let z = index / ((size[0] - 1) * (size[1] - 1));
+            let corners = CORNERS.map(|c| [x + c[0], y + c[1], z + c[2]]);
+            TETRAHEDRA
+                .iter()
+                .flat_map(|tetrahedron| {
+                    let points = tetrahedron.map(|c| corners[c]);
+                    tetrahedron_surface(points.map(|p| (position(p), value(p), p)), iso)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let cavity = calculate::volume(&inner);
+    result.resin = (solid - cavity).max(0.0);
+    result.inner = IndexedMesh::from_triangles(&inner);
+
+    if let Some(diameter) = settings.drain_diameter {
+        let (count, shells) = result.inner.shells();
+        let mut lowest: Vec<Option<[f32; 3]>> = vec![None; count];
+        for (face, &shell) in result.inner.faces.iter().zip(shells.iter()) {
+            for &v in face {
+                let p = result.inner.vertices[v as usize];
+                let slot = &mut lowest[shell as usize];
+                if slot.is_none_or(|q| p[2] < q[2]) {
+                    *slot = Some(p);
+                }
+            }
+        }
+
+        for start in lowest.into_iter().flatten() {
+            let ray = Ray {
+                origin: start,
+                direction: [0.0, 0.0, -1.0],
+            };
+            if let Some(hit) = bvh.ray(&ray, f32::INFINITY) {
+                result.resin =
+                    (result.resin - PI * diameter * diameter / 4.0 * hit.distance).max(0.0);
+                result.drain_holes.push(DrainHole {
+                    position: ray.at(hit.distance),
+                    depth: hit.distance,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// Triangles where the field crosses `iso` inside one tetrahedron, facing towards
+/// the higher values. Edge points are interpolated from the corner with the smaller
+/// grid index so both cells sharing an edge produce bit identical vertices.
+fn tetrahedron_surface(corners: [([f32; 3], f32, [usize; 3]); 4], iso: f32) -> Vec<Triangle> {
+    let above: Vec<usize> = (0..4).filter(|&i| corners[i].1 > iso).collect();
+    let below: Vec<usize> = (0..4).filter(|&i| corners[i].1 <= iso).collect();
+    if above.is_empty() || below.is_empty() {
+        return Vec::new();
+    }
+
+    let crossing = |a: usize, b: usize| -> [f32; 3] {
+        let (a, b) = if corners[a].2 < corners[b].2 {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let (pa, va, _) = corners[a];
+        let (pb, vb, _) = corners[b];
+        let t = (iso - va) / (vb - va);
+        std::array::from_fn(|axis| pa[axis] + (pb[axis] - pa[axis]) * t)
+    };
+
+    let mut polygon = Vec::new();
+    if above.len() == 2 {
+        // quad, walk the four crossing edges in order around it
+        let (a, b, c, d) = (above[0], above[1], below[0], below[1]);
+        polygon.extend([
+            crossing(a, c),
+            crossing(a, d),
+            crossing(b, d),
+            crossing(b, c),
+        ]);
+    } else {
+        let (single, others) = if above.len() == 1 {
+            (above[0], &below)
+        } else {
+            (below[0], &above)
+        };
+        polygon.extend(others.iter().map(|&o| crossing(single, o)));
+    }
+
+    // orient towards the higher values
+    let centre = |set: &[usize]| -> nalgebra::Vector3<f32> {
+        set.iter()
+            .map(|&i| nalgebra::Vector3::from(corners[i].0))
+            .sum::<nalgebra::Vector3<f32>>()
+            / set.len() as f32
+    };
+    let uphill = centre(&above) - centre(&below);
+
+    let mut triangles = Vec::with_capacity(2);
+    for k in 1..polygon.len() - 1 {
+        let (p, q, r) = (polygon[0], polygon[k], polygon[k + 1]);
+        let normal = (nalgebra::Vector3::from(q) - nalgebra::Vector3::from(p))
+            .cross(&(nalgebra::Vector3::from(r) - nalgebra::Vector3::from(p)));
+        triangles.push(if normal.dot(&uphill) >= 0.0 {
+            Triangle::new([p, q, r])
+        } else {
+            Triangle::new([p, r, q])
+        });
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::calculate::test_support::cube;
+
+    #[test]
+    fn hollow_cube() {
+        let settings = Settings {
+            wall_thickness: 2.0,
+            drain_diameter: None,
+        };
+        let result = hollow(&cube([0.0; 3], [20.0; 3]), &settings);
+        assert!((result.solid - 8000.0).abs() < 1e-2);
+        let expected = 8000.0 - 16.0f32.powi(3);
+        assert!(
+            (result.resin - expected).abs() < expected * 0.03,
+            "resin {}",
+            result.resin
+        );
+        assert!(result.drain_holes.is_empty());
+
+        // every edge of the inner wall is shared by two faces
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for face in &result.inner.faces {
+            for k in 0..3 {
+                let (a, b) = (face[k], face[(k + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        assert!(!edges.is_empty());
+        assert!(edges.values().all(|&count| count == 2));
+    }
+
+    #[test]
+    fn drain_hole_through_the_floor() {
+        let settings = Settings {
+            wall_thickness: 2.0,
+            drain_diameter: Some(2.0),
+        };
+        let result = hollow(&cube([0.0; 3], [20.0; 3]), &settings);
+        assert_eq!(result.drain_holes.len(), 1);
+        let hole = result.drain_holes[0];
+        assert!((hole.depth - 2.0).abs() < 0.3, "depth {}", hole.depth);
+        assert!(hole.position[2].abs() < 1e-3);
+    }
+
+    #[test]
+    fn thick_walls_leave_it_solid() {
+        let settings = Settings {
+            wall_thickness: 6.0,
+            drain_diameter: Some(2.0),
+        };
+        let result = hollow(&cube([0.0; 3], [10.0; 3]), &settings);
+        assert!(result.inner.faces.is_empty());
+        assert!(result.drain_holes.is_empty());
+        assert_eq!(result.resin, result.solid);
+    }
+}
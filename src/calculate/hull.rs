@@ -0,0 +1,298 @@
+// Convex hull
+//
+// Quickhull in double precision over the welded vertices. Starting from a
+// tetrahedron of extreme points, every face keeps the points in front of it; the
+// farthest of those is added by removing all faces it can see and connecting it to
+// the horizon they leave behind. Faces find their neighbours through a map of
+// directed edges, and points within `EPSILON` of a plane count as lying on it, which
+// keeps coplanar facets of CAD models from producing slivers. Models without volume
+// (a single plane or line) have an empty hull.
+
+use std::collections::{HashMap, HashSet};
+
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use crate::model::{Triangle, mesh::IndexedMesh};
+
+/// tolerance relative to the size of the model
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Default)]
+pub struct Hull {
+    /// outward facing triangles of the hull
+    pub mesh: IndexedMesh,
+    /// enclosed volume (mm³)
+    pub volume: f32,
+    /// surface area (mm²)
+    pub area: f32,
+}
+
+struct Face {
+    vertices: [usize; 3],
+    normal: Vector3<f64>,
+    offset: f64,
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl Face {
+    fn new(points: &[Vector3<f64>], vertices: [usize; 3]) -> Self {
+        let [a, b, c] = vertices.map(|i| points[i]);
+        let normal = (b - a).cross(&(c - a)).normalize();
+        Self {
+            vertices,
+            normal,
+            offset: normal.dot(&a),
+            outside: Vec::new(),
+            alive: true,
+        }
+    }
+
+    fn distance(&self, point: &Vector3<f64>) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+pub fn convex_hull(triangles: &[Triangle]) -> Hull {
+    let welded = IndexedMesh::from_triangles(triangles);
+    let points: Vec<Vector3<f64>> = welded
+        .vertices
+        .iter()
+        .map(|v| Vector3::new(v[0] as f64, v[1] as f64, v[2] as f64))
+        .collect();
+    if points.len() < 4 {
+        return Hull::default();
+    }
+
+    let extent = (0..3)
+        .map(|axis| {
+            let (min, max) = points.iter().fold((f64::MAX, f64::MIN), |(min, max), p| {
+                (min.min(p[axis]), max.max(p[axis]))
+            });
+            max - min
+        })
+        .fold(0.0, f64::max);
+    let epsilon = EPSILON * extent.max(1.0);
+
+    let Some(simplex) = initial_simplex(&points, epsilon) else {
+        return Hull::default();
+    };
+
+    let mut faces: Vec<Face> = Vec::new();
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    let add_face = |faces: &mut Vec<Face>,
+                    edges: &mut HashMap<(usize, usize), usize>,
+                    vertices: [usize; 3]| {
+        let face = Face::new(&points, vertices);
+        for edge in face.edges() {
+            edges.insert(edge, faces.len());
+        }
+        faces.push(face);
+        faces.len() - 1
+    };
+
+    // orient the tetrahedron so every face points away from the fourth vertex
+    let [a, b, c, d] = simplex;
+    let (b, c) = if Face::new(&points, [a, b, c]).distance(&points[d]) > 0.0 {
+        (c, b)
+    } else {
+        (b, c)
+    };
+    for vertices in [[a, b, c], [a, d, b], [b, d, c], [c, d, a]] {
+        add_face(&mut faces, &mut edges, vertices);
+    }
+
+    // hand every remaining point to the first face that sees it
+    let assignments: Vec<Option<usize>> = (0..points.len())
+        .into_par_iter()
+        .map(|i| {
+            if simplex.contains(&i) {
+                return None;
+            }
+            (0..4).find(|&f| faces[f].distance(&points[i]) > epsilon)
+        })
+        .collect();
+    for (i, face) in assignments.into_iter().enumerate() {
+        if let Some(face) = face {
+            faces[face].outside.push(i);
+        }
+    }
+
+    let mut pending: Vec<usize> = (0..4).filter(|&f| !faces[f].outside.is_empty()).collect();
+    while let Some(start) = pending.pop() {
+        if !faces[start].alive || faces[start].outside.is_empty() {
+            continue;
+        }
+        let apex = *faces[start]
+            .outside
+            .iter()
+            .max_by(|&&p, &&q| {
+                let face = &faces[start];
+                face.distance(&points[p])
+                    .total_cmp(&face.distance(&points[q]))
+            })
+            .unwrap_or(&faces[start].outside[0]);
+
+        // faces visible from the apex, found by walking across shared edges
+        let mut visible = vec![start];
+        let mut seen = HashSet::from([start]);
+        let mut horizon = Vec::new();
+        let mut cursor = 0;
+        while cursor < visible.len() {
+            let face = visible[cursor];
+            cursor += 1;
+            for (from, to) in faces[face].edges() {
+                let Some(&neighbour) = edges.get(&(to, from)) else {
+                    continue;
+                };
+                if seen.contains(&neighbour) {
+                    continue;
+                }
+                if faces[neighbour].distance(&points[apex]) > epsilon {
+                    seen.insert(neighbour);
+                    visible.push(neighbour);
+                } else {
+                    horizon.push((from, to));
+                }
+            }
+        }
+
+        let mut orphans = Vec::new();
+        for &face in visible.iter() {
+            faces[face].alive = false;
+            orphans.append(&mut faces[face].outside);
+            for edge in faces[face].edges() {
+                if edges.get(&edge) == Some(&face) {
+                    edges.remove(&edge);
+                }
+            }
+        }
+
+        let created: Vec<usize> = horizon
+            .into_iter()
+            .map(|(from, to)| add_face(&mut faces, &mut edges, [from, to, apex]))
+            .collect();
+
+        for point in orphans.into_iter().filter(|&p| p != apex) {
+            if let Some(&face) = created
+                .iter()
+                .find(|&&f| faces[f].distance(&points[point]) > epsilon)
+            {
+                faces[face].outside.push(point);
+            }
+        }
+        pending.extend(
+            created
+                .into_iter()
+                .filter(|&f| !faces[f].outside.is_empty()),
+        );
+    }
+
+    // compact the surviving faces into a mesh
+    let mut index: HashMap<usize, u32> = HashMap::new();
+    let mut mesh = IndexedMesh::default();
+    let (mut volume, mut area) = (0.0f64, 0.0f64);
+    let origin = points[a];
+    for face in faces.iter().filter(|face| face.alive) {
+        let [p, q, r] = face.vertices.map(|i| points[i] - origin);
+        let cross = (q - p).cross(&(r - p));
+        area += cross.norm() / 2.0;
+        volume += p.dot(&q.cross(&r)) / 6.0;
+
+        mesh.faces.push(face.vertices.map(|i| {
+            *index.entry(i).or_insert_with(|| {
+                mesh.vertices.push(welded.vertices[i]);
+                (mesh.vertices.len() - 1) as u32
+            })
+        }));
+    }
+
+    Hull {
+        mesh,
+        volume: volume as f32,
+        area: area as f32,
+    }
+}
+
+/// four points spanning a tetrahedron of non-zero volume
+fn initial_simplex(points: &[Vector3<f64>], epsilon: f64) -> Option<[usize; 4]> {
+    let farthest = |score: &dyn Fn(&Vector3<f64>) -> f64| {
+        (0..points.len())
+            .max_by(|&i, &j| score(&points[i]).total_cmp(&score(&points[j])))
+            .unwrap_or(0)
+    };
+
+    // widest pair among the extreme points along the axes
+    let extremes: Vec<usize> = (0..3)
+        .flat_map(|axis| {
+            [
+                farthest(&|p: &Vector3<f64>| -p[axis]),
+                farthest(&|p: &Vector3<f64>| p[axis]),
+            ]
+        })
+        .collect();
+    let (a, b) = extremes
+        .iter()
+        .flat_map(|&i| extremes.iter().map(move |&j| (i, j)))
+        .max_by(|&(i, j), &(k, l)| {
+            (points[i] - points[j])
+                .norm_squared()
+                .total_cmp(&(points[k] - points[l]).norm_squared())
+        })?;
+    if (points[a] - points[b]).norm() <= epsilon {
+        return None;
+    }
+
+    let line = (points[b] - points[a]).normalize();
+    let c = farthest(&|p: &Vector3<f64>| (p - points[a]).cross(&line).norm());
+    if (points[c] - points[a]).cross(&line).norm() <= epsilon {
+        return None;
+    }
+
+    let normal = (points[b] - points[a])
+        .cross(&(points[c] - points[a]))
+        .normalize();
+    let d = farthest(&|p: &Vector3<f64>| (p - points[a]).dot(&normal).abs());
+    if (points[d] - points[a]).dot(&normal).abs() <= epsilon {
+        return None;
+    }
+
+    Some([a, b, c, d])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate::test_support::cube;
+
+    #[test]
+    fn hull_of_a_cube() {
+        let hull = convex_hull(&cube([0.0; 3], [10.0; 3]));
+        assert!((hull.volume - 1000.0).abs() < 1e-2);
+        assert!((hull.area - 600.0).abs() < 1e-2);
+        assert!((crate::calculate::volume(&hull.mesh.to_triangles()) - 1000.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn hull_bridges_separate_parts() {
+        let mut triangles = cube([0.0; 3], [1.0; 3]);
+        triangles.extend(cube([2.0, 0.0, 0.0], [3.0, 1.0, 1.0]));
+        let hull = convex_hull(&triangles);
+        assert!((hull.volume - 3.0).abs() < 1e-4);
+        assert!((hull.area - 14.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn flat_models_have_no_hull() {
+        let square: Vec<Triangle> = cube([0.0; 3], [1.0; 3]).into_iter().take(2).collect();
+        let hull = convex_hull(&square);
+        assert_eq!(hull.volume, 0.0);
+        assert!(hull.mesh.faces.is_empty());
+    }
+}
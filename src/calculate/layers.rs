@@ -0,0 +1,128 @@
+// Layer stacks
+//
+// Adaptive layers follow the cusp height rule: a face whose normal makes an angle
+// with Z shows a stair step of `height * |n.z|`, so every layer is made as tall as
+// possible while keeping that step under the minimum layer height for all faces it
+// crosses. Vertical walls get the maximum height, shallow slopes the minimum, and
+// perfectly flat faces are ignored as they show no steps at all.
+
+use rayon::prelude::*;
+
+use crate::{error::AppError, model};
+
+/// faces flatter than this are treated as horizontal
+const HORIZONTAL: f32 = 0.999;
+/// most layers in a stack, taller stacks are refused
+pub const MAX_LAYERS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LayerHeights {
+    Fixed(f32),
+    Adaptive { min: f32, max: f32 },
+}
+
+/// a single printed layer, all values in mm
+#[derive(Debug, Clone, Copy)]
+pub struct Slab {
+    pub bottom: f32,
+    pub height: f32,
+}
+
+impl Slab {
+    pub fn top(&self) -> f32 {
+        self.bottom + self.height
+    }
+
+    /// height the layer is sliced at
+    pub fn middle(&self) -> f32 {
+        self.bottom + self.height / 2.0
+    }
+}
+
+/// Split `z_min..z_max` into layers, failing beyond `MAX_LAYERS`. Heights are added
+/// up in f64, f32 stops moving once the model is tall enough.
+pub fn stack(
+    triangles: &[model::Triangle],
+    z_min: f32,
+    z_max: f32,
+    heights: &LayerHeights,
+) -> Result<Vec<Slab>, AppError> {
+    match *heights {
+        LayerHeights::Fixed(height) => fixed(z_min, z_max, height),
+        LayerHeights::Adaptive { min, max } => adaptive(triangles, z_min, z_max, min, max),
+    }
+}
+
+fn fixed(z_min: f32, z_max: f32, height: f32) -> Result<Vec<Slab>, AppError> {
+    let count = ((z_max as f64 - z_min as f64) / height as f64)
+        .ceil()
+        .max(0.0);
+    if count > MAX_LAYERS as f64 {
+        return Err(too_many_layers());
+    }
+    Ok((0..count as usize)
+        .map(|i| Slab {
+            bottom: (z_min as f64 + i as f64 * height as f64) as f32,
+            height,
+        })
+        .collect())
+}
+
+fn adaptive(
+    triangles: &[model::Triangle],
+    z_min: f32,
+    z_max: f32,
+    min: f32,
+    max: f32,
+) -> Result<Vec<Slab>, AppError> {
+    // (bottom, top, allowed height) of every sloped face, sorted by bottom
+    let mut faces: Vec<(f32, f32, f32)> = triangles
+        .par_iter()
+        .filter_map(|t| {
+            let [a, b, c] = t.vertices().map(nalgebra::Vector3::from);
+            let normal = (b - a).cross(&(c - a)).try_normalize(0.0)?;
+            let nz = normal.z.abs();
+            if nz > HORIZONTAL {
+                return None;
+            }
+            let allowed = if nz > 0.0 { min / nz } else { max };
+            let bottom = a.z.min(b.z).min(c.z);
+            let top = a.z.max(b.z).max(c.z);
+            Some((bottom, top, allowed.clamp(min, max)))
+        })
+        .collect();
+    faces.par_sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut slabs = Vec::new();
+    let mut active: Vec<(f32, f32, f32)> = Vec::new();
+    let mut next = 0;
+    let mut bottom = z_min as f64;
+    while bottom < z_max as f64 {
+        if slabs.len() == MAX_LAYERS {
+            return Err(too_many_layers());
+        }
+        let z = bottom as f32;
+        while next < faces.len() && faces[next].0 < z + max {
+            active.push(faces[next]);
+            next += 1;
+        }
+        active.retain(|face| face.1 > z);
+
+        let mut height = max;
+        for face in active.iter() {
+            if face.0 < z + height {
+                height = height.min(face.2);
+            }
+        }
+        let height = height.max(min);
+
+        slabs.push(Slab { bottom: z, height });
+        bottom += height as f64;
+    }
+
+    Ok(slabs)
+}
+
+fn too_many_layers() -> AppError {
+    AppError::bad_request(format!("too many layers, the limit is {}", MAX_LAYERS))
+}
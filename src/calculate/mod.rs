@@ -1,3 +1,20 @@
+pub mod adhesion;
+pub mod bridges;
+pub mod bvh;
+pub mod cavities;
+pub mod decimate;
+pub mod estimate;
+pub mod hollow;
+pub mod hull;
+pub mod layers;
+pub mod nesting;
+pub mod quote;
+pub mod raster;
+pub mod skin;
+pub mod slice;
+#[cfg(test)]
+pub(crate) mod test_support;
+
 use crate::model;
 use rayon::prelude::*;
 
@@ -9,3 +26,65 @@ pub fn volume(triangles: &[model::Triangle]) -> f32 {
 
     total_volume.abs()
 }
+
+/// Volume of every material of `materials`, triangles without a material are left
+/// out. Materials forming closed shells of their own get their exact volume, when
+/// they only cover patches of a shared surface the volumes are taken relative to the
+/// centre of the model, which splits it sensibly for most painted models.
+pub fn volumes_by_material(
+    triangles: &[model::Triangle],
+    materials: &model::material::Materials,
+) -> Vec<f32> {
+    let (min, max) = bounds(triangles);
+    let centre = nalgebra::Vector3::from(std::array::from_fn(|axis| (min[axis] + max[axis]) / 2.0));
+
+    let mut volumes = vec![0.0f32; materials.materials.len()];
+    for (triangle, material) in triangles.iter().zip(materials.assignment.iter()) {
+        if let Some(material) = material {
+            let [a, b, c] = triangle
+                .vertices()
+                .map(|v| nalgebra::Vector3::from(v) - centre);
+            volumes[*material as usize] += a.dot(&b.cross(&c)) / 6.0;
+        }
+    }
+
+    volumes.into_iter().map(f32::abs).collect()
+}
+
+/// Surface area of every material of `materials`.
+pub fn areas_by_material(
+    triangles: &[model::Triangle],
+    materials: &model::material::Materials,
+) -> Vec<f32> {
+    let mut areas = vec![0.0f32; materials.materials.len()];
+    for (triangle, material) in triangles.iter().zip(materials.assignment.iter()) {
+        if let Some(material) = material {
+            let [a, b, c] = triangle.vertices().map(nalgebra::Vector3::from);
+            areas[*material as usize] += (b - a).cross(&(c - a)).norm() / 2.0;
+        }
+    }
+
+    areas
+}
+
+/// axis aligned bounding box of the model as `(min, max)`
+pub fn bounds(triangles: &[model::Triangle]) -> ([f32; 3], [f32; 3]) {
+    let empty = || ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+    triangles
+        .par_iter()
+        .fold(empty, |(mut min, mut max), triangle| {
+            for v in triangle.vertices() {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(v[axis]);
+                    max[axis] = max[axis].max(v[axis]);
+                }
+            }
+            (min, max)
+        })
+        .reduce(empty, |a, b| {
+            (
+                std::array::from_fn(|axis| a.0[axis].min(b.0[axis])),
+                std::array::from_fn(|axis| a.1[axis].max(b.1[axis])),
+            )
+        })
+}
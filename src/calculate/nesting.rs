@@ -0,0 +1,425 @@
+// Build plate nesting
+//
+// Every part is reduced to the convex hull of its XY projection and then to the
+// minimum-area rectangle enclosing that hull (rotating calipers over the hull edges).
+// The rectangles are packed onto plates with a first-fit shelf packer, trying both
+// 0 and 90 degree orientations, opening a new plate when nothing fits.
+
+use rayon::prelude::*;
+
+use crate::{error::AppError, model};
+
+/// printer build plate, all dimensions in mm
+#[derive(Debug, Clone, Copy)]
+pub struct Bed {
+    pub width: f32,
+    pub depth: f32,
+    /// minimum gap kept between parts and between parts and the plate edge
+    pub spacing: f32,
+}
+
+impl Default for Bed {
+    fn default() -> Self {
+        Self {
+            width: 220.0,
+            depth: 220.0,
+            spacing: 5.0,
+        }
+    }
+}
+
+/// XY outline of a part along with its minimum-area bounding rectangle
+#[derive(Debug, Clone)]
+pub struct Footprint {
+    /// convex hull of the part projected onto the XY plane (counter-clockwise)
+    pub outline: Vec<[f32; 2]>,
+    /// rotation about Z (degrees) that aligns the bounding rectangle with the axes
+    pub angle: f32,
+    pub width: f32,
+    pub depth: f32,
+}
+
+impl Footprint {
+    pub fn new(triangles: &[model::Triangle]) -> Self {
+        let points: Vec<[f64; 2]> = triangles
+            .par_iter()
+            .flat_map_iter(|triangle| {
+                triangle
+                    .vertices()
+                    .iter()
+                    .map(|v| [v[0] as f64, v[1] as f64])
+            })
+            .collect();
+
+        Self::from_points(points)
+    }
+
+    pub fn from_points(points: Vec<[f64; 2]>) -> Self {
+        let hull = convex_hull(points);
+        let (angle, width, depth) = min_area_rect(&hull);
+
+        Self {
+            outline: hull.iter().map(|p| [p[0] as f32, p[1] as f32]).collect(),
+            angle: angle.to_degrees() as f32,
+            width: width as f32,
+            depth: depth as f32,
+        }
+    }
+
+    /// min corner of the outline after rotating it by `degrees` about the origin
+    fn rotated_min(&self, degrees: f32) -> [f32; 2] {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        self.outline
+            .iter()
+            .map(|p| [p[0] * cos - p[1] * sin, p[0] * sin + p[1] * cos])
+            .fold([f32::INFINITY, f32::INFINITY], |acc, p| {
+                [acc[0].min(p[0]), acc[1].min(p[1])]
+            })
+    }
+}
+
+/// where a single part ends up
+///
+/// The part is first rotated by `rotation` degrees about the Z axis through the
+/// origin and then translated by `offset`.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// index into the footprints passed to [`nest`]
+    pub part: usize,
+    pub plate: usize,
+    pub rotation: f32,
+    pub offset: [f32; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct Nesting {
+    pub plates: usize,
+    pub placements: Vec<Placement>,
+}
+
+struct Shelf {
+    y: f32,
+    height: f32,
+    cursor: f32,
+}
+
+struct Plate {
+    shelves: Vec<Shelf>,
+    used: f32,
+}
+
+pub fn nest(footprints: &[Footprint], bed: &Bed) -> Result<Nesting, AppError> {
+    let usable_width = bed.width - 2.0 * bed.spacing;
+    let usable_depth = bed.depth - 2.0 * bed.spacing;
+
+    let mut order: Vec<usize> = (0..footprints.len()).collect();
+    order.sort_by(|&a, &b| {
+        let a = footprints[a].width.max(footprints[a].depth);
+        let b = footprints[b].width.max(footprints[b].depth);
+        b.total_cmp(&a)
+    });
+
+    let mut plates: Vec<Plate> = Vec::new();
+    let mut placements = Vec::with_capacity(footprints.len());
+
+    for part in order {
+        let footprint = &footprints[part];
+        if footprint.outline.is_empty() {
+            return Err(AppError::bad_request("part has no triangles to nest"));
+        }
+        // widest side along X first, so shelves stay as short as possible
+        let (long, short) = if footprint.width >= footprint.depth {
+            (footprint.width, footprint.depth)
+        } else {
+            (footprint.depth, footprint.width)
+        };
+        let orientations = [(long, short), (short, long)];
+        if !orientations
+            .iter()
+            .any(|&(w, d)| w <= usable_width && d <= usable_depth)
+        {
+            return Err(AppError::bad_request(format!(
+                "part does not fit on the build plate ({:.1} x {:.1} mm)",
+                bed.width, bed.depth
+            )));
+        }
+
+        let existing = plates.iter_mut().enumerate().find_map(|(index, plate)| {
+            place_on_plate(
+                plate,
+                &orientations,
+                usable_width,
+                usable_depth,
+                bed.spacing,
+            )
+            .map(|slot| (index, slot))
+        });
+        let slot = match existing {
+            Some(slot) => slot,
+            None => {
+                let mut plate = Plate {
+                    shelves: Vec::new(),
+                    used: 0.0,
+                };
+                let slot = place_on_plate(
+                    &mut plate,
+                    &orientations,
+                    usable_width,
+                    usable_depth,
+                    bed.spacing,
+                )
+                .ok_or_else(|| AppError::bad_request("part does not fit on the build plate"))?;
+                plates.push(plate);
+                (plates.len() - 1, slot)
+            }
+        };
+
+        let (plate, (x, y, w, _)) = slot;
+        let rotation = if w == footprint.width {
+            footprint.angle
+        } else {
+            footprint.angle + 90.0
+        };
+        let min = footprint.rotated_min(rotation);
+
+        placements.push(Placement {
+            part,
+            plate,
+            rotation: rotation.rem_euclid(360.0),
+            offset: [bed.spacing + x - min[0], bed.spacing + y - min[1]],
+        });
+    }
+
+    placements.sort_by_key(|p| p.part);
+
+    Ok(Nesting {
+        plates: plates.len(),
+        placements,
+    })
+}
+
+/// returns the `(x, y, width, depth)` slot relative to the usable area of the plate
+fn place_on_plate(
+    plate: &mut Plate,
+    orientations: &[(f32, f32); 2],
+    usable_width: f32,
+    usable_depth: f32,
+    spacing: f32,
+) -> Option<(f32, f32, f32, f32)> {
+    for shelf in plate.shelves.iter_mut() {
+        for &(w, d) in orientations {
+            if d <= shelf.height && shelf.cursor + w <= usable_width {
+                let slot = (shelf.cursor, shelf.y, w, d);
+                shelf.cursor += w + spacing;
+                return Some(slot);
+            }
+        }
+    }
+
+    let y = if plate.shelves.is_empty() {
+        0.0
+    } else {
+        plate.used + spacing
+    };
+    for &(w, d) in orientations {
+        if w <= usable_width && y + d <= usable_depth {
+            plate.shelves.push(Shelf {
+                y,
+                height: d,
+                cursor: w + spacing,
+            });
+            plate.used = y + d;
+            return Some((0.0, y, w, d));
+        }
+    }
+
+    None
+}
+
+/// Andrew's monotone chain, counter-clockwise without collinear points
+fn convex_hull(mut points: Vec<[f64; 2]>) -> Vec<[f64; 2]> {
+    points.par_sort_unstable_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    fn cross(o: &[f64; 2], a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+    }
+
+    let mut hull: Vec<[f64; 2]> = Vec::with_capacity(points.len() * 2);
+    for p in points.iter() {
+        while hull.len() >= 2 && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(*p);
+    }
+    let lower = hull.len() + 1;
+    for p in points.iter().rev().skip(1) {
+        while hull.len() >= lower && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(*p);
+    }
+    hull.pop();
+
+    hull
+}
+
+/// `(angle, width, depth)` of the minimum-area rectangle enclosing a convex polygon,
+/// where `angle` rotates the polygon so the rectangle becomes axis aligned
+fn min_area_rect(hull: &[[f64; 2]]) -> (f64, f64, f64) {
+    let extent = |angle: f64| {
+        let (sin, cos) = angle.sin_cos();
+        let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+        for p in hull {
+            let x = p[0] * cos - p[1] * sin;
+            let y = p[0] * sin + p[1] * cos;
+            min = [min[0].min(x), min[1].min(y)];
+            max = [max[0].max(x), max[1].max(y)];
+        }
+        (max[0] - min[0], max[1] - min[1])
+    };
+
+    if hull.len() < 3 {
+        let (width, depth) = extent(0.0);
+        return (0.0, width.max(0.0), depth.max(0.0));
+    }
+
+    (0..hull.len())
+        .map(|i| {
+            let a = hull[i];
+            let b = hull[(i + 1) % hull.len()];
+            // rotate the edge onto the X axis
+            let angle = -(b[1] - a[1]).atan2(b[0] - a[0]);
+            let (width, depth) = extent(angle);
+            (angle, width, depth)
+        })
+        .min_by(|a, b| (a.1 * a.2).total_cmp(&(b.1 * b.2)))
+        .unwrap_or((0.0, 0.0, 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// corners of a `width` by `depth` rectangle turned by `degrees`, with its
+    /// centre and a point inside that are no part of the hull
+    fn rectangle(width: f64, depth: f64, degrees: f64, at: [f64; 2]) -> Vec<[f64; 2]> {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        [
+            [0.0, 0.0],
+            [width, 0.0],
+            [width, depth],
+            [0.0, depth],
+            [width / 2.0, depth / 2.0],
+            [width / 3.0, depth / 4.0],
+        ]
+        .map(|p| {
+            [
+                at[0] + p[0] * cos - p[1] * sin,
+                at[1] + p[0] * sin + p[1] * cos,
+            ]
+        })
+        .to_vec()
+    }
+
+    /// outline of a placed part, rotated and moved onto its plate
+    fn placed(footprint: &Footprint, placement: &Placement) -> ([f32; 2], [f32; 2]) {
+        let (sin, cos) = placement.rotation.to_radians().sin_cos();
+        footprint
+            .outline
+            .iter()
+            .map(|p| {
+                [
+                    p[0] * cos - p[1] * sin + placement.offset[0],
+                    p[0] * sin + p[1] * cos + placement.offset[1],
+                ]
+            })
+            .fold(
+                ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]),
+                |(min, max), p| {
+                    (
+                        [min[0].min(p[0]), min[1].min(p[1])],
+                        [max[0].max(p[0]), max[1].max(p[1])],
+                    )
+                },
+            )
+    }
+
+    #[test]
+    fn rotated_rectangle() {
+        let footprint = Footprint::from_points(rectangle(40.0, 10.0, 30.0, [5.0, -3.0]));
+        assert_eq!(footprint.outline.len(), 4);
+        let mut sides = [footprint.width, footprint.depth];
+        sides.sort_by(f32::total_cmp);
+        assert!((sides[0] - 10.0).abs() < 1e-3 && (sides[1] - 40.0).abs() < 1e-3);
+
+        // turned by the angle the outline is axis aligned with those sides
+        let placement = Placement {
+            part: 0,
+            plate: 0,
+            rotation: footprint.angle,
+            offset: [0.0, 0.0],
+        };
+        let (min, max) = placed(&footprint, &placement);
+        assert!((max[0] - min[0] - footprint.width).abs() < 1e-3);
+        assert!((max[1] - min[1] - footprint.depth).abs() < 1e-3);
+    }
+
+    #[test]
+    fn parts_that_do_not_fit() {
+        let bed = Bed::default();
+        let long = Footprint::from_points(rectangle(215.0, 10.0, 0.0, [0.0, 0.0]));
+        let error = nest(&[long], &bed).unwrap_err().to_string();
+        assert!(error.contains("does not fit on the build plate"), "{error}");
+
+        // diagonally it would fit, but the packer keeps parts aligned with the bed
+        let diagonal = Footprint::from_points(rectangle(260.0, 5.0, 45.0, [0.0, 0.0]));
+        assert!(nest(&[diagonal], &bed).is_err());
+
+        let empty = Footprint::from_points(Vec::new());
+        assert!(nest(&[empty], &bed).is_err());
+    }
+
+    #[test]
+    fn placements_stay_apart_and_on_the_bed() {
+        let bed = Bed::default();
+        let footprints: Vec<Footprint> = (0..40)
+            .map(|i| {
+                let width = 20.0 + (i * 37 % 90) as f64;
+                let depth = 10.0 + (i * 53 % 60) as f64;
+                Footprint::from_points(rectangle(width, depth, (i * 17) as f64, [i as f64, 0.0]))
+            })
+            .collect();
+        let nesting = nest(&footprints, &bed).unwrap();
+        assert!(nesting.plates > 1);
+        assert_eq!(nesting.placements.len(), footprints.len());
+
+        let boxes: Vec<(usize, [f32; 2], [f32; 2])> = nesting
+            .placements
+            .iter()
+            .map(|placement| {
+                let (min, max) = placed(&footprints[placement.part], placement);
+                (placement.plate, min, max)
+            })
+            .collect();
+        let tolerance = 1e-2;
+        for &(plate, min, max) in &boxes {
+            assert!(plate < nesting.plates);
+            assert!(min[0] >= bed.spacing - tolerance && min[1] >= bed.spacing - tolerance);
+            assert!(max[0] <= bed.width - bed.spacing + tolerance);
+            assert!(max[1] <= bed.depth - bed.spacing + tolerance);
+        }
+        for (i, a) in boxes.iter().enumerate() {
+            for b in &boxes[i + 1..] {
+                let apart = (0..2).any(|axis| {
+                    a.2[axis] + bed.spacing <= b.1[axis] + tolerance
+                        || b.2[axis] + bed.spacing <= a.1[axis] + tolerance
+                });
+                assert!(a.0 != b.0 || apart, "{a:?} overlaps {b:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// Quantity aware quote
+//
+// Costs that scale with every copy (the material) are kept apart from those paid
+// once per job: the setup fee and the preparation of every build plate the nested
+// copies need. Splitting a job over fewer plates therefore lowers the unit price.
+// Multi-material parts are priced per material.
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pricing {
+    /// paid once per job
+    pub setup_fee: f32,
+    /// paid for every build plate used
+    pub plate_fee: f32,
+}
+
+/// material going into a single copy
+#[derive(Debug, Clone, Copy)]
+pub struct Line {
+    /// material used (mm³)
+    pub volume: f32,
+    /// price of one cm³ of the material
+    pub price: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Quote {
+    pub quantity: u32,
+    pub plates: usize,
+    /// cost of every line for a single copy
+    pub lines: Vec<f32>,
+    pub per_copy: f32,
+    pub per_job: f32,
+    pub total: f32,
+}
+
+impl Quote {
+    pub fn unit_price(&self) -> f32 {
+        self.total / self.quantity.max(1) as f32
+    }
+}
+
+/// Quote `quantity` copies made of `lines`, nested onto `plates` plates.
+pub fn quote(lines: &[Line], quantity: u32, plates: usize, pricing: &Pricing) -> Quote {
+    let lines: Vec<f32> = lines
+        .iter()
+        .map(|line| line.volume / 1000.0 * line.price)
+        .collect();
+    let per_copy = lines.iter().sum::<f32>();
+    let per_job = pricing.setup_fee + pricing.plate_fee * plates as f32;
+
+    Quote {
+        quantity,
+        plates,
+        lines,
+        per_copy,
+        per_job,
+        total: per_copy * quantity as f32 + per_job,
+    }
+}
@@ -0,0 +1,151 @@
+// Layer masks
+//
+// Boolean operations between layers are done on rasterised sections rather than on
+// the polygons themselves, which keeps them robust against the self-intersecting
+// and open contours found in real uploads. Accuracy is bounded by the grid cell.
+
+use crate::calculate::slice::{Grid, Layer};
+
+#[derive(Debug, Clone)]
+pub struct Mask {
+    pub grid: Grid,
+    pub cells: Vec<bool>,
+}
+
+impl Mask {
+    pub fn new(layer: &Layer, grid: &Grid) -> Self {
+        Self {
+            grid: *grid,
+            cells: layer.coverage(grid),
+        }
+    }
+
+    pub fn empty(grid: &Grid) -> Self {
+        Self {
+            grid: *grid,
+            cells: vec![false; grid.width * grid.height],
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.cells.iter().filter(|&&c| c).count()
+    }
+
+    /// covered area in mm²
+    pub fn area(&self) -> f32 {
+        self.count() as f32 * self.grid.cell * self.grid.cell
+    }
+
+    /// cells in `self` but not in `other`
+    pub fn difference(&self, other: &Mask) -> Mask {
+        self.combine(other, |a, b| a && !b)
+    }
+
+    pub fn intersection(&self, other: &Mask) -> Mask {
+        self.combine(other, |a, b| a && b)
+    }
+
+    pub fn union(&self, other: &Mask) -> Mask {
+        self.combine(other, |a, b| a || b)
+    }
+
+    fn combine(&self, other: &Mask, op: impl Fn(bool, bool) -> bool) -> Mask {
+        Mask {
+            grid: self.grid,
+            cells: self
+                .cells
+                .iter()
+                .zip(other.cells.iter())
+                .map(|(&a, &b)| op(a, b))
+                .collect(),
+        }
+    }
+
+    /// grow the mask by `radius` mm using a disc shaped element
+    pub fn dilate(&self, radius: f32) -> Mask {
+        let r = (radius / self.grid.cell).round() as isize;
+        if r <= 0 {
+            return self.clone();
+        }
+
+        let offsets: Vec<(isize, isize)> = (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|(dx, dy)| dx * dx + dy * dy <= r * r)
+            .collect();
+
+        let (width, height) = (self.grid.width as isize, self.grid.height as isize);
+        let mut cells = vec![false; self.cells.len()];
+        for (index, _) in self.cells.iter().enumerate().filter(|(_, c)| **c) {
+            let (x, y) = (index as isize % width, index as isize / width);
+            for (dx, dy) in offsets.iter() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx >= 0 && ny >= 0 && nx < width && ny < height {
+                    cells[(ny * width + nx) as usize] = true;
+                }
+            }
+        }
+
+        Mask {
+            grid: self.grid,
+            cells,
+        }
+    }
+
+    /// 8-connected regions as lists of cell indices
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.cells.len()];
+        let mut components = Vec::new();
+
+        for start in 0..self.cells.len() {
+            if !self.cells[start] || seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut component = Vec::new();
+            let mut stack = vec![start];
+            while let Some(index) = stack.pop() {
+                component.push(index);
+                for neighbour in self.neighbours(index) {
+                    if self.cells[neighbour] && !seen[neighbour] {
+                        seen[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// the up to eight cells surrounding `index`
+    pub fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> + use<> {
+        let (width, height) = (self.grid.width as isize, self.grid.height as isize);
+        let (x, y) = (index as isize % width, index as isize / width);
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx != 0 || dy != 0)
+            .map(move |(dx, dy)| (x + dx, y + dy))
+            .filter(move |&(nx, ny)| nx >= 0 && ny >= 0 && nx < width && ny < height)
+            .map(move |(nx, ny)| (ny * width + nx) as usize)
+    }
+
+    /// cell holding the point `p` in mm, `None` outside the grid
+    pub fn index(&self, p: [f32; 2]) -> Option<usize> {
+        let x = ((p[0] - self.grid.origin[0]) / self.grid.cell).floor();
+        let y = ((p[1] - self.grid.origin[1]) / self.grid.cell).floor();
+        if x < 0.0 || y < 0.0 || x >= self.grid.width as f32 || y >= self.grid.height as f32 {
+            return None;
+        }
+        Some(y as usize * self.grid.width + x as usize)
+    }
+
+    /// centre of a cell in mm
+    pub fn position(&self, index: usize) -> [f32; 2] {
+        let (x, y) = (index % self.grid.width, index / self.grid.width);
+        [
+            self.grid.origin[0] + (x as f32 + 0.5) * self.grid.cell,
+            self.grid.origin[1] + (y as f32 + 0.5) * self.grid.cell,
+        ]
+    }
+}
@@ -0,0 +1,194 @@
+// Top and bottom skins
+//
+// A part of a layer has to be printed solid when any of the layers within the skin
+// thickness below it leaves that part uncovered (bottom skin), or any of the layers
+// within the skin thickness above it does (top skin). Outside the model counts as
+// uncovered, so the first and last layers are always solid.
+//
+// Rather than intersecting every mask of the window for every layer, the number of
+// window layers covering each cell is kept while the window slides up the stack;
+// every layer is added once as it enters and removed once as it leaves.
+
+use std::ops::Range;
+
+use crate::calculate::{layers::Slab, raster::Mask, slice::Grid};
+
+/// The layers a layer is compared with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// layers within the skin thickness below and above, the layer itself included
+    pub layers: Range<usize>,
+    /// whether the window reaches below the first or above the last layer
+    pub outside: bool,
+}
+
+/// Layers within `thickness` below and above layer `i`.
+pub fn window(stack: &[Slab], i: usize, thickness: f32) -> Window {
+    let slab = stack[i];
+    let mut outside = false;
+
+    let mut first = i;
+    loop {
+        let next = first.checked_sub(1);
+        let gap = slab.bottom - next.map_or(stack[0].bottom, |k| stack[k].top());
+        if gap >= thickness {
+            break;
+        }
+        match next {
+            Some(k) => first = k,
+            None => {
+                outside = true;
+                break;
+            }
+        }
+    }
+
+    let mut last = i;
+    loop {
+        let next = (last + 1 < stack.len()).then_some(last + 1);
+        let gap = next.map_or(stack[stack.len() - 1].top(), |k| stack[k].bottom) - slab.top();
+        if gap >= thickness {
+            break;
+        }
+        match next {
+            Some(k) => last = k,
+            None => {
+                outside = true;
+                break;
+            }
+        }
+    }
+
+    Window {
+        layers: first..last + 1,
+        outside,
+    }
+}
+
+/// Number of layers of the current window covering every cell.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    counts: Vec<u32>,
+    layers: Range<usize>,
+}
+
+impl Coverage {
+    pub fn new(grid: &Grid) -> Self {
+        Self {
+            counts: vec![0; grid.width * grid.height],
+            layers: 0..0,
+        }
+    }
+
+    /// layers counted at the moment
+    pub fn layers(&self) -> Range<usize> {
+        self.layers.clone()
+    }
+
+    /// Move the window up to `layers`, neither end of a window may move down.
+    pub fn slide<'a>(&mut self, layers: Range<usize>, mask: impl Fn(usize) -> &'a Mask) {
+        for j in self.layers.start..layers.start.min(self.layers.end) {
+            self.update(mask(j), |count| count - 1);
+        }
+        for j in self.layers.end.max(layers.start)..layers.end {
+            self.update(mask(j), |count| count + 1);
+        }
+        self.layers = layers;
+    }
+
+    fn update(&mut self, mask: &Mask, op: impl Fn(u32) -> u32) {
+        for (count, _) in self
+            .counts
+            .iter_mut()
+            .zip(mask.cells.iter())
+            .filter(|(_, covered)| **covered)
+        {
+            *count = op(*count);
+        }
+    }
+
+    /// Solid part of `layer`, whose window the coverage is at.
+    pub fn skin(&self, layer: &Mask, outside: bool) -> Mask {
+        let all = self.layers.len() as u32;
+        Mask {
+            grid: layer.grid,
+            cells: layer
+                .cells
+                .iter()
+                .zip(self.counts.iter())
+                .map(|(&covered, &count)| covered && (outside || count < all))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(heights: &[f32]) -> Vec<Slab> {
+        let mut bottom = 0.0;
+        heights
+            .iter()
+            .map(|&height| {
+                let slab = Slab { bottom, height };
+                bottom += height;
+                slab
+            })
+            .collect()
+    }
+
+    #[test]
+    fn windows_within_the_thickness() {
+        let stack = stack(&[0.2; 10]);
+        assert_eq!(
+            window(&stack, 0, 0.5),
+            Window {
+                layers: 0..4,
+                outside: true
+            }
+        );
+        assert_eq!(
+            window(&stack, 5, 0.5),
+            Window {
+                layers: 2..9,
+                outside: false
+            }
+        );
+        assert_eq!(window(&stack, 8, 0.5).layers, 5..10);
+        assert!(window(&stack, 8, 0.5).outside);
+    }
+
+    #[test]
+    fn sliding_matches_intersection() {
+        let grid = Grid::covering([0.0, 0.0], [3.0, 3.0], 1.0);
+        let cells = grid.width * grid.height;
+        // cell c is covered by layer i unless c is a multiple of i + 2
+        let masks: Vec<Mask> = (0..12)
+            .map(|i| Mask {
+                grid,
+                cells: (0..cells).map(|c| c % (i + 2) != 0).collect(),
+            })
+            .collect();
+        let stack = stack(&[0.2; 12]);
+
+        let mut coverage = Coverage::new(&grid);
+        for i in 0..masks.len() {
+            let window = window(&stack, i, 0.5);
+            coverage.slide(window.layers.clone(), |j| &masks[j]);
+
+            let interior = window.layers.clone().fold(masks[i].clone(), |covered, j| {
+                covered.intersection(&masks[j])
+            });
+            let expected = if window.outside {
+                masks[i].clone()
+            } else {
+                masks[i].difference(&interior)
+            };
+            assert_eq!(
+                coverage.skin(&masks[i], window.outside).cells,
+                expected.cells
+            );
+        }
+    }
+}
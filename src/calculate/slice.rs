@@ -0,0 +1,253 @@
+// Planar slicing
+//
+// Triangles are bucketed by the layers they span, every layer intersects only its
+// own bucket and the resulting segments are chained into contours. Segments are
+// oriented from the facet normal so outer contours run counter-clockwise and holes
+// clockwise (seen from +Z), which makes the summed signed area the filled area.
+
+use std::collections::HashMap;
+
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use crate::model;
+
+/// cross-section of the model at a single height
+#[derive(Debug, Clone, Default)]
+pub struct Layer {
+    pub z: f32,
+    pub contours: Vec<Vec<[f32; 2]>>,
+}
+
+/// axis aligned raster laid over the XY plane, row 0 starts at `origin`
+#[derive(Debug, Clone, Copy)]
+pub struct Grid {
+    pub origin: [f32; 2],
+    /// size of a cell in mm
+    pub cell: f32,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Grid {
+    /// grid covering `min..max` with an extra cell of margin on every side
+    pub fn covering(min: [f32; 2], max: [f32; 2], cell: f32) -> Self {
+        let width = ((max[0] - min[0]) / cell).ceil().max(0.0) as usize + 2;
+        let height = ((max[1] - min[1]) / cell).ceil().max(0.0) as usize + 2;
+        Self {
+            origin: [min[0] - cell, min[1] - cell],
+            cell,
+            width,
+            height,
+        }
+    }
+}
+
+impl Layer {
+    /// area enclosed by the contours (holes subtracted)
+    pub fn area(&self) -> f32 {
+        self.contours
+            .iter()
+            .map(|contour| signed_area(contour))
+            .sum::<f32>()
+            .abs()
+    }
+
+    /// total length of the contours
+    pub fn perimeter(&self) -> f32 {
+        self.contours.iter().map(|contour| length(contour)).sum()
+    }
+
+    /// cells whose centre lies inside the section, using the even-odd rule
+    pub fn coverage(&self, grid: &Grid) -> Vec<bool> {
+        let mut cells = vec![false; grid.width * grid.height];
+
+        cells
+            .par_chunks_mut(grid.width)
+            .enumerate()
+            .for_each(|(row, cells)| {
+                let y = grid.origin[1] + (row as f32 + 0.5) * grid.cell;
+                let mut crossings: Vec<f32> = self
+                    .contours
+                    .iter()
+                    .flat_map(|contour| {
+                        let n = contour.len();
+                        (0..n).filter_map(move |i| {
+                            let (a, b) = (contour[i], contour[(i + 1) % n]);
+                            // half-open so shared vertices are only counted once
+                            ((a[1] <= y) != (b[1] <= y))
+                                .then(|| a[0] + (y - a[1]) / (b[1] - a[1]) * (b[0] - a[0]))
+                        })
+                    })
+                    .collect();
+                crossings.sort_unstable_by(f32::total_cmp);
+
+                for span in crossings.chunks_exact(2) {
+                    let first = ((span[0] - grid.origin[0]) / grid.cell - 0.5)
+                        .ceil()
+                        .max(0.0) as usize;
+                    let last = ((span[1] - grid.origin[0]) / grid.cell - 0.5).ceil();
+                    let last = (last.max(0.0) as usize).min(grid.width);
+                    if first < last {
+                        cells[first..last].fill(true);
+                    }
+                }
+            });
+
+        cells
+    }
+}
+
+/// shoelace area of a closed contour, positive when counter-clockwise
+pub fn signed_area(contour: &[[f32; 2]]) -> f32 {
+    let n = contour.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f32>()
+        / 2.0
+}
+
+/// length of a closed contour
+pub fn length(contour: &[[f32; 2]]) -> f32 {
+    let n = contour.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            (b[0] - a[0]).hypot(b[1] - a[1])
+        })
+        .sum()
+}
+
+/// Cut the model at every height in `heights`, which must be sorted ascending.
+pub fn slice(triangles: &[model::Triangle], heights: &[f32]) -> Vec<Layer> {
+    // bucket every triangle into the layers it spans, stored as offsets + indices
+    let spans: Vec<(usize, usize)> = triangles
+        .par_iter()
+        .map(|t| {
+            let z = t.vertices().map(|v| v[2]);
+            let (min, max) = (z[0].min(z[1]).min(z[2]), z[0].max(z[1]).max(z[2]));
+            let first = heights.partition_point(|&h| h < min);
+            let last = heights.partition_point(|&h| h <= max);
+            (first, last)
+        })
+        .collect();
+
+    let mut offsets = vec![0usize; heights.len() + 1];
+    for &(first, last) in spans.iter() {
+        for layer in first..last {
+            offsets[layer + 1] += 1;
+        }
+    }
+    for i in 1..offsets.len() {
+        offsets[i] += offsets[i - 1];
+    }
+    let mut cursor = offsets.clone();
+    let mut buckets = vec![0u32; offsets[heights.len()]];
+    for (index, &(first, last)) in spans.iter().enumerate() {
+        for layer in first..last {
+            buckets[cursor[layer]] = index as u32;
+            cursor[layer] += 1;
+        }
+    }
+
+    heights
+        .par_iter()
+        .enumerate()
+        .map(|(layer, &z)| {
+            let segments: Vec<[[f32; 2]; 2]> = buckets[offsets[layer]..offsets[layer + 1]]
+                .iter()
+                .filter_map(|&index| intersect(&triangles[index as usize], z))
+                .collect();
+            Layer {
+                z,
+                contours: chain(segments),
+            }
+        })
+        .collect()
+}
+
+/// segment where the triangle crosses the plane, oriented with the solid on its left
+fn intersect(triangle: &model::Triangle, z: f32) -> Option<[[f32; 2]; 2]> {
+    let v = triangle.vertices();
+    // vertices lying exactly on the plane count as above it, so every crossing
+    // edge has one endpoint strictly below and one at or above
+    let above = v.map(|p| p[2] >= z);
+    let count = above.iter().filter(|&&a| a).count();
+    if count == 0 || count == 3 {
+        return None;
+    }
+
+    let mut points = Vec::with_capacity(2);
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        if above[i] != above[j] {
+            // interpolate from a canonical endpoint so neighbours produce identical points
+            let (a, b) = if v[i] < v[j] {
+                (v[i], v[j])
+            } else {
+                (v[j], v[i])
+            };
+            let t = (z - a[2]) / (b[2] - a[2]);
+            points.push([a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])]);
+        }
+    }
+    let (p, q) = (points[0], points[1]);
+    if p == q {
+        return None;
+    }
+
+    let [a, b, c] = v.map(Vector3::from);
+    let normal = (b - a).cross(&(c - a));
+    // direction of travel with the interior on the left is z x n
+    let direction = [-normal.y, normal.x];
+    if (q[0] - p[0]) * direction[0] + (q[1] - p[1]) * direction[1] >= 0.0 {
+        Some([p, q])
+    } else {
+        Some([q, p])
+    }
+}
+
+/// join segments sharing endpoints into contours
+fn chain(segments: Vec<[[f32; 2]; 2]>) -> Vec<Vec<[f32; 2]>> {
+    let key = |p: &[f32; 2]| p.map(|c| (c + 0.0).to_bits());
+    let mut starts: HashMap<[u32; 2], Vec<usize>> = HashMap::with_capacity(segments.len());
+    for (index, segment) in segments.iter().enumerate() {
+        starts.entry(key(&segment[0])).or_default().push(index);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut contours = Vec::new();
+    for first in 0..segments.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+
+        let mut contour = vec![segments[first][0]];
+        let mut end = segments[first][1];
+        let start = key(&segments[first][0]);
+        while key(&end) != start {
+            let next = starts
+                .get(&key(&end))
+                .and_then(|candidates| candidates.iter().find(|&&i| !used[i]).copied());
+            let Some(next) = next else {
+                break;
+            };
+            used[next] = true;
+            contour.push(end);
+            end = segments[next][1];
+        }
+        if key(&end) != start {
+            contour.push(end);
+        }
+
+        if contour.len() >= 3 {
+            contours.push(contour);
+        }
+    }
+
+    contours
+}
@@ -0,0 +1,39 @@
+// Fixtures shared by the geometry tests
+
+use crate::model::Triangle;
+
+/// axis aligned box from `min` to `max` with outward facing triangles
+pub fn cube(min: [f32; 3], max: [f32; 3]) -> Vec<Triangle> {
+    let corner = |i: usize| {
+        [0, 1, 2].map(|axis| {
+            if (i >> axis) & 1 == 0 {
+                min[axis]
+            } else {
+                max[axis]
+            }
+        })
+    };
+    [
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+    ]
+    .into_iter()
+    .flat_map(|q| [[q[0], q[1], q[2]], [q[0], q[2], q[3]]])
+    .map(|t| Triangle::new(t.map(corner)))
+    .collect()
+}
+
+/// the same triangles facing the other way
+pub fn flipped(triangles: Vec<Triangle>) -> Vec<Triangle> {
+    triangles
+        .into_iter()
+        .map(|t| {
+            let [a, b, c] = *t.vertices();
+            Triangle::new([a, c, b])
+        })
+        .collect()
+}
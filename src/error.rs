@@ -132,6 +132,21 @@ impl AppError {
         Self::Other(anyhow::Error::new(err))
     }
 
+    /// the same error with `context` in front of its message, like the file it is about
+    pub fn with_context(mut self, context: impl std::fmt::Display) -> Self {
+        match &mut self {
+            AppError::BadRequest { user_message, .. }
+            | AppError::NotFound { user_message, .. }
+            | AppError::Conflict { user_message, .. }
+            | AppError::UniqueViolation { user_message, .. }
+            | AppError::Unauthorized { user_message, .. } => {
+                *user_message = format!("{} {}", context, user_message);
+            }
+            _ => {}
+        }
+        self
+    }
+
     fn source_error(&self) -> Option<&anyhow::Error> {
         match self {
             AppError::BadRequest { source, .. }
@@ -221,4 +236,5 @@ impl_from_error!(
     std::string::FromUtf8Error,
     yup_oauth2::Error,
     reqwest::Error,
+    png::EncodingError,
 );
@@ -1,4 +1,7 @@
 pub mod model;
+pub mod nesting;
+pub mod preview;
+pub mod render;
 
 use axum::{
     Json,
@@ -1,7 +1,25 @@
+use crate::calculate::{
+    adhesion::Adhesion,
+    cavities, estimate, hollow, hull,
+    layers::LayerHeights,
+    nesting::{self, Bed, Footprint},
+    quote::{self, Line, Pricing},
+};
 use crate::config::ENV;
 use crate::error::AppError;
-use crate::model::MeshParser;
-use crate::models::mdl::{CalculateVolumeReq, CalculateVolumeRes};
+use crate::model::{
+    MeshParser,
+    archive::Budget,
+    compression::{Download, Encoding},
+    material::Material,
+    mesh,
+    transform::Transform,
+};
+use crate::models::mdl::{
+    ArchiveFileRes, BridgeRes, BuildItemRes, CalculateArchiveRes, CalculateVolumeReq,
+    CalculateVolumeRes, CavityRes, DrainHoleRes, EstimateRes, HollowRes, HullRes, LineItemRes,
+    MaterialCostRes, MaterialRes, PrintSettingsReq, QuoteRes, TransformReq, VolumeRes,
+};
 use crate::{calculate, model, models};
 use axum::Extension;
 use axum::{
@@ -9,16 +27,55 @@ use axum::{
     http::{StatusCode, header},
     response::IntoResponse,
 };
-use bytes::BytesMut;
+use bytes::Bytes;
 use futures_util::StreamExt;
 use validator::Validate;
 
 const MAX_MODEL_FILE_SIZE: usize = 100 * 1024 * 1024; // 100MB
+const MAX_MATERIAL_LIBRARY_SIZE: usize = 1024 * 1024; // 1MB
+const MAX_MATERIAL_LIBRARIES: usize = 4;
 
 /// Calculate the volume of a 3D model file stored in S3.
 ///
-/// The model file must be in STL format and not exceed 100MB in size.
+/// The model file must be in STL, OBJ, 3MF, AMF, PLY, glTF/GLB or OFF format and not exceed 100MB in size.
+/// It may be uploaded gzip or zstd compressed, the limit then applies once it is
+/// decompressed.
 /// The volume is calculated based on the provided unit (mm, cm, m).
+/// A geometry fingerprint is returned alongside it, so re-uploads of the same model
+/// can be recognised and earlier results reused. An optional `transform` scales,
+/// mirrors, rotates and moves the model before any of the other calculations, the
+/// fingerprint is taken of the model as uploaded.
+///
+/// With `convex_hull` set the volume and area of the convex hull are reported as
+/// well, and with `detect_cavities` the sealed internal voids that would trap resin
+/// in an SLA print. `hollow` settings add the resin needed when the part is hollowed
+/// for SLA, with drain holes drilled at the lowest points.
+///
+/// When `print` settings are given the model is also sliced, with a fixed or
+/// adaptive layer height, to estimate the extruded material and print time. The
+/// material includes the solid top and bottom skins, and the bridges found between
+/// consecutive layers are reported.
+///
+/// With `quote` pricing the response includes a quote for `quantity` copies, split
+/// into the material cost of every copy and the setup and plate costs of the job,
+/// where the copies are nested onto as few build plates as possible. A copy uses the
+/// resin of the hollowed part, or the extruded material and adhesion when the model
+/// is sliced. When a copy does not fit on the plate the quote is left out and
+/// `quote_error` says why.
+///
+/// OBJ files using `usemtl` get their volume and area split per material, with the
+/// colours read from the `mtllib` files stored next to the model. 3MF files are split
+/// the same way by their base materials and colour groups, AMF files by the material
+/// of their volumes and coloured PLY and OFF files by their colours. Every material
+/// can be priced on its own through `material_prices`.
+///
+/// 3MF packages are assembled from their build items, components and transforms
+/// included, and the volume of every build item is reported next to the total.
+///
+/// A zip archive is analysed model by model: every supported file inside gets its
+/// own results, or the reason it could not be read, and OBJ files find their `mtllib`
+/// files in the archive. Archives are limited in entries, decompressed size and
+/// compression ratio.
 ///
 /// This endpoint requires authentication. The user's access token must be
 /// provided in the Authorization header as a Bearer token.
@@ -28,8 +85,8 @@ const MAX_MODEL_FILE_SIZE: usize = 100 * 1024 * 1024; // 100MB
     tag = "Model Calculations",
     request_body = CalculateVolumeReq,
     responses(
-        (status = 200, description = "Volume calculated successfully", body = CalculateVolumeRes),
-        (status = 400, description = "Bad Request (file too large, invalid format, validation error)", body = models::error::ResponseError),
+        (status = 200, description = "Volume calculated successfully", body = VolumeRes),
+        (status = 400, description = "Bad Request (file too large, invalid format, part larger than the plate, validation error)", body = models::error::ResponseError),
         (status = 404, description = "Model not found, or related error", body = models::error::ResponseError),
         (status = 500, description = "Internal Server Error", body = models::error::ResponseError),
     ),
@@ -43,19 +100,477 @@ pub async fn calculate_volume(
 ) -> Result<impl IntoResponse, AppError> {
     payload.validate()?;
 
-    let url = format!(
-        "https://{}.s3.{}.amazonaws.com/{}/orders/{}/{}/{}",
-        ENV.s3_bucket_name,
-        ENV.s3_region,
-        user_id,
-        payload.order_id,
-        payload.item_id,
-        payload.file_name,
+    let url = model_url(
+        &user_id,
+        &payload.order_id,
+        &payload.item_id,
+        &payload.file_name,
+    );
+    let (format, bytes) = fetch_model(&url).await?;
+    // parsing and every analysis are kept off the async workers, an archive holds up
+    // to MAX_ARCHIVE_MODELS models
+    let res = match format {
+        model::Format::ZIP => {
+            VolumeRes::Archive(blocking(move || analyse_archive(&payload, &bytes)).await?)
+        }
+        model::Format::OBJ => {
+            let obj = blocking(move || model::obj::parse(&bytes)).await?;
+            let library = fetch_libraries(
+                &user_id,
+                &payload.order_id,
+                &payload.item_id,
+                &obj.libraries,
+            )
+            .await;
+            VolumeRes::Model(Box::new(
+                blocking(move || analyse(&payload, Loaded::obj(obj, &library))).await?,
+            ))
+        }
+        _ => VolumeRes::Model(Box::new(
+            blocking(move || {
+                analyse(
+                    &payload,
+                    load_model(&format, &bytes, &mut Budget::default())?,
+                )
+            })
+            .await?,
+        )),
+    };
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(res),
+    ))
+}
+
+/// Run CPU bound work, like parsing or analysing a model, on the blocking pool so
+/// it does not hold up the async workers.
+pub(crate) async fn blocking<T, F>(work: F) -> Result<T, AppError>
+where
+    F: FnOnce() -> Result<T, AppError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(AppError::from_generic_error)?
+}
+
+/// triangles of a model with the materials and build items its format describes
+struct Loaded {
+    triangles: Vec<model::Triangle>,
+    materials: Option<model::material::Materials>,
+    items: Vec<model::threemf::Item>,
+}
+
+impl Loaded {
+    fn obj(obj: model::obj::Obj, library: &[Material]) -> Self {
+        Self {
+            triangles: obj.mesh.to_triangles(),
+            materials: obj.materials(library),
+            items: Vec::new(),
+        }
+    }
+}
+
+/// Parse a model, OBJ files without their material libraries. Packages read their
+/// parts within `budget`.
+fn load_model(
+    format: &model::Format,
+    bytes: &[u8],
+    budget: &mut Budget,
+) -> Result<Loaded, AppError> {
+    let (triangles, materials) = match format {
+        model::Format::ThreeMF => {
+            let package = model::threemf::parse_with_budget(bytes, budget)?;
+            return Ok(Loaded {
+                triangles: package.triangles,
+                materials: package.materials,
+                items: package.items,
+            });
+        }
+        model::Format::OBJ => return Ok(Loaded::obj(model::obj::parse(bytes)?, &[])),
+        model::Format::AMF => {
+            let amf = model::amf::parse_with_budget(bytes, budget)?;
+            (amf.triangles, amf.materials)
+        }
+        model::Format::PLY => {
+            let ply = model::ply::parse(bytes)?;
+            (ply.mesh.to_triangles(), ply.materials())
+        }
+        model::Format::GLTF => {
+            let gltf = model::gltf::parse(bytes)?;
+            (gltf.triangles, gltf.materials)
+        }
+        model::Format::OFF => {
+            let off = model::off::parse(bytes)?;
+            (off.mesh.to_triangles(), off.materials())
+        }
+        _ => (parse_model(format, bytes)?, None),
+    };
+    Ok(Loaded {
+        triangles,
+        materials,
+        items: Vec::new(),
+    })
+}
+
+/// Analyse every model of an archive on its own, a model that fails does not fail
+/// the others. OBJ files take their material libraries from the archive, and 3MF or
+/// zipped AMF files read their parts within what is left of the archive budget.
+fn analyse_archive(
+    payload: &CalculateVolumeReq,
+    bytes: &[u8],
+) -> Result<CalculateArchiveRes, AppError> {
+    let archive = model::archive::models(bytes)?;
+    let mut budget = archive.budget;
+    let files = archive
+        .models
+        .into_iter()
+        .map(|entry| {
+            let result = match entry.format {
+                // the same check a single upload passes before it is parsed
+                _ if !entry.format.validate_bytes(&entry.bytes) => {
+                    Err(AppError::bad_request("invalid model file"))
+                }
+                model::Format::OBJ => model::obj::parse(&entry.bytes).map(|obj| {
+                    let library: Vec<Material> = obj
+                        .libraries
+                        .iter()
+                        .filter_map(|library| archive.libraries.get(&entry.name, library))
+                        .flat_map(model::mtl::parse)
+                        .collect();
+                    Loaded::obj(obj, &library)
+                }),
+                _ => load_model(&entry.format, &entry.bytes, &mut budget),
+            }
+            .and_then(|loaded| analyse(payload, loaded));
+
+            let (result, error) = match result {
+                Ok(res) => (Some(res), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            ArchiveFileRes {
+                file_name: entry.name,
+                format: entry.format.as_str().to_string(),
+                result,
+                error,
+            }
+        })
+        .collect();
+
+    Ok(CalculateArchiveRes {
+        status: "success".to_string(),
+        files,
+        skipped: archive.skipped,
+    })
+}
+
+/// every calculation `payload` asks for on one model
+fn analyse(payload: &CalculateVolumeReq, model: Loaded) -> Result<CalculateVolumeRes, AppError> {
+    let Loaded {
+        mut triangles,
+        materials,
+        items,
+    } = model;
+    // the fingerprint is of the upload itself, so a copy requested at another scale
+    // or orientation is still recognised
+    let fingerprint = model::fingerprint::fingerprint(&triangles, payload.translation_invariant);
+    if let Some(transform) = payload.transform.as_ref().map(transform_model)
+        && !transform.is_identity()
+    {
+        transform.apply(&mut triangles);
+        // scaled and moved far enough, finite coordinates overflow
+        mesh::check_triangles(&triangles)?;
+    }
+
+    let volume = calculate::volume(&triangles);
+    let mut res = CalculateVolumeRes::new(
+        triangles.len(),
+        unit_volume(volume, &payload.unit),
+        fingerprint,
     );
+
+    if !items.is_empty() {
+        res = res.with_items(
+            items
+                .iter()
+                .map(|item| BuildItemRes {
+                    name: item.name.clone(),
+                    object: item.object,
+                    volume: unit_volume(
+                        calculate::volume(&triangles[item.faces.clone()]),
+                        &payload.unit,
+                    ),
+                })
+                .collect(),
+        );
+    }
+
+    // (name, volume) of every material of multi-material models
+    let material_volumes: Vec<(String, f32)> = match materials.as_ref() {
+        Some(materials) => materials
+            .materials
+            .iter()
+            .map(|material| material.name.clone())
+            .zip(calculate::volumes_by_material(&triangles, materials))
+            .collect(),
+        None => Vec::new(),
+    };
+    if let Some(materials) = materials.as_ref() {
+        let areas = calculate::areas_by_material(&triangles, materials);
+        res = res.with_materials(
+            materials
+                .materials
+                .iter()
+                .zip(material_volumes.iter())
+                .zip(areas)
+                .map(|((material, (_, volume)), area)| MaterialRes {
+                    name: material.name.clone(),
+                    volume: unit_volume(*volume, &payload.unit),
+                    area: unit_area(area, &payload.unit),
+                    color: material.color,
+                })
+                .collect(),
+        );
+    }
+
+    if payload.convex_hull {
+        let hull = hull::convex_hull(&triangles);
+        res = res.with_hull(HullRes {
+            volume: unit_volume(hull.volume, &payload.unit),
+            area: unit_area(hull.area, &payload.unit),
+            volume_ratio: if volume > 0.0 {
+                hull.volume / volume
+            } else {
+                0.0
+            },
+        });
+    }
+
+    if payload.detect_cavities {
+        res = res.with_cavities(
+            cavities::cavities(&triangles)?
+                .into_iter()
+                .map(|cavity| CavityRes {
+                    volume: unit_volume(cavity.volume, &payload.unit),
+                    min: cavity.bounds.min,
+                    max: cavity.bounds.max,
+                })
+                .collect(),
+        );
+    }
+
+    // material used by a single copy: the resin of the hollowed part for SLA, or the
+    // extruded material including adhesion when the model is sliced
+    let mut material = volume;
+
+    if let Some(req) = payload.hollow.as_ref() {
+        let hollowing = hollow::hollow(
+            &triangles,
+            &hollow::Settings {
+                wall_thickness: req.wall_thickness,
+                drain_diameter: req.drain_diameter,
+            },
+        );
+        material = hollowing.resin;
+        res = res.with_hollow(HollowRes {
+            resin_volume: unit_volume(hollowing.resin, &payload.unit),
+            solid_volume: unit_volume(hollowing.solid, &payload.unit),
+            drain_holes: hollowing
+                .drain_holes
+                .iter()
+                .map(|hole| DrainHoleRes {
+                    position: hole.position,
+                    depth: hole.depth,
+                })
+                .collect(),
+        });
+    }
+
+    if let Some(print) = payload.print.as_ref() {
+        let estimate = estimate::estimate(&triangles, &print_settings(print))?;
+        material = estimate.material
+            + estimate
+                .adhesion
+                .iter()
+                .map(|item| item.material)
+                .sum::<f32>();
+        let z_min = calculate::bounds(&triangles).0[2];
+        let heights = estimate.layers.iter().map(|slab| slab.height);
+        res = res.with_estimate(EstimateRes {
+            layers: estimate.layers.len(),
+            min_layer_height: heights.clone().reduce(f32::min).unwrap_or(0.0),
+            max_layer_height: heights.reduce(f32::max).unwrap_or(0.0),
+            material_volume: unit_volume(estimate.material, &payload.unit),
+            skin_volume: unit_volume(estimate.skin, &payload.unit),
+            print_time: estimate.time,
+            adhesion: estimate
+                .adhesion
+                .iter()
+                .map(|item| LineItemRes {
+                    name: item.kind.as_str().to_string(),
+                    material_volume: unit_volume(item.material, &payload.unit),
+                    print_time: item.time,
+                })
+                .collect(),
+            bridges: estimate
+                .bridges
+                .iter()
+                .map(|bridge| BridgeRes {
+                    z: bridge.z - z_min,
+                    area: unit_area(bridge.area, &payload.unit),
+                    span: unit_length(bridge.span, &payload.unit),
+                })
+                .collect(),
+        });
+    }
+
+    if let Some(req) = payload.quote.as_ref() {
+        let footprints = vec![Footprint::new(&triangles); payload.quantity as usize];
+        let bed = Bed {
+            width: req.bed_width,
+            depth: req.bed_depth,
+            spacing: req.spacing,
+        };
+        // a part that fits no plate leaves the rest of the results intact
+        let plates = match nesting::nest(&footprints, &bed) {
+            Ok(nesting) => nesting.plates,
+            Err(e) => return Ok(res.with_quote_error(e.to_string())),
+        };
+        let pricing = Pricing {
+            setup_fee: req.setup_fee,
+            plate_fee: req.plate_fee,
+        };
+
+        // every material gets its share of the material used by a copy
+        let total: f32 = material_volumes.iter().map(|(_, volume)| volume).sum();
+        let lines: Vec<Line> = if total > 0.0 {
+            material_volumes
+                .iter()
+                .map(|(name, volume)| Line {
+                    volume: material * volume / total,
+                    price: req
+                        .material_prices
+                        .get(name)
+                        .copied()
+                        .unwrap_or(req.material_price),
+                })
+                .collect()
+        } else {
+            vec![Line {
+                volume: material,
+                price: req.material_price,
+            }]
+        };
+
+        let quote = quote::quote(&lines, payload.quantity, plates, &pricing);
+        res = res.with_quote(QuoteRes {
+            quantity: quote.quantity,
+            plates: quote.plates,
+            materials: if total > 0.0 {
+                material_volumes
+                    .iter()
+                    .zip(quote.lines.iter())
+                    .map(|((name, _), cost)| MaterialCostRes {
+                        name: name.clone(),
+                        per_copy: *cost,
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            per_copy: quote.per_copy,
+            per_job: quote.per_job,
+            total: quote.total,
+            unit_price: quote.unit_price(),
+        });
+    }
+
+    Ok(res)
+}
+
+fn transform_model(req: &TransformReq) -> Transform {
+    Transform::identity()
+        .scale(req.scale.factors())
+        .mirror(req.mirror)
+        .rotate(req.rotation)
+        .translate(req.translation)
+}
+
+/// convert a volume in mm³ into the requested unit
+fn unit_volume(volume: f32, unit: &str) -> f32 {
+    match unit {
+        "mm" => volume,
+        "cm" => volume / 1000.0,
+        "m" => volume / 1_000_000.0,
+        _ => volume,
+    }
+}
+
+/// convert an area in mm² into the requested unit
+fn unit_area(area: f32, unit: &str) -> f32 {
+    match unit {
+        "mm" => area,
+        "cm" => area / 100.0,
+        "m" => area / 1_000_000.0,
+        _ => area,
+    }
+}
+
+/// convert a length in mm into the requested unit
+fn unit_length(length: f32, unit: &str) -> f32 {
+    match unit {
+        "mm" => length,
+        "cm" => length / 10.0,
+        "m" => length / 1000.0,
+        _ => length,
+    }
+}
+
+fn print_settings(req: &PrintSettingsReq) -> estimate::Settings {
+    estimate::Settings {
+        layer_heights: if req.adaptive_layers {
+            LayerHeights::Adaptive {
+                min: req.min_layer_height,
+                max: req.max_layer_height,
+            }
+        } else {
+            LayerHeights::Fixed(req.layer_height)
+        },
+        line_width: req.line_width,
+        walls: req.wall_count,
+        infill: req.infill_density,
+        speed: req.print_speed,
+        skin_thickness: req.skin_thickness,
+        adhesion: req.adhesion.as_ref().map(|adhesion| Adhesion {
+            brim_width: adhesion.brim_width,
+            skirt_lines: adhesion.skirt_lines,
+            skirt_distance: adhesion.skirt_distance,
+            raft_layers: adhesion.raft_layers,
+            raft_margin: adhesion.raft_margin,
+        }),
+    }
+}
+
+pub(crate) fn model_url(
+    user_id: &models::user::UserId,
+    order_id: &str,
+    item_id: &str,
+    file_name: &str,
+) -> String {
+    format!(
+        "https://{}.s3.{}.amazonaws.com/{}/orders/{}/{}/{}",
+        ENV.s3_bucket_name, ENV.s3_region, user_id, order_id, item_id, file_name,
+    )
+}
+
+/// download a model from S3 and work out its format
+pub(crate) async fn fetch_model(url: &str) -> Result<(model::Format, Bytes), AppError> {
     let client = reqwest::Client::new();
 
     let head_response = client
-        .head(&url)
+        .head(url)
         .send()
         .await
         .map_err(|e| AppError::bad_request_with_source("failed to fetch model metadata", e))?;
@@ -83,63 +598,212 @@ pub async fn calculate_volume(
         .get(reqwest::header::CONTENT_TYPE)
         .and_then(|ct| ct.to_str().ok())
         .and_then(model::Format::from_content_type)
-        .or_else(|| model::Format::from_url(&url));
+        .or_else(|| model::Format::from_url(url));
 
-    let response = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| AppError::bad_request_with_source("failed to fetch model", e))?;
+    let bytes = download(&client, url, "model", MAX_MODEL_FILE_SIZE).await?;
+
+    let format = format
+        .or_else(|| model::Format::from_magic_bytes(&bytes))
+        .ok_or_else(|| AppError::bad_request("unsupported model format"))?;
+    if !format.validate_bytes(&bytes) {
+        return Err(AppError::bad_request("invalid model file"));
+    }
+
+    Ok((format, bytes))
+}
+
+/// Stream `url` into memory, failing once more than `limit` bytes arrive. Gzip and
+/// zstd compressed files are decompressed as they arrive, the limit then applies to
+/// the decompressed size as well.
+async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    what: &str,
+    limit: usize,
+) -> Result<Bytes, AppError> {
+    let response =
+        client.get(url).send().await.map_err(|e| {
+            AppError::bad_request_with_source(format!("failed to fetch {}", what), e)
+        })?;
     let status = response.status();
     if !status.is_success() {
         let error = match status {
-            reqwest::StatusCode::NOT_FOUND => AppError::not_found("model not found"),
-            _ => AppError::bad_request("failed to fetch model"),
+            reqwest::StatusCode::NOT_FOUND => AppError::not_found(format!("{} not found", what)),
+            _ => AppError::bad_request(format!("failed to fetch {}", what)),
         };
         return Err(error);
     }
 
+    let encoding = response
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Encoding::from_content_encoding);
+    let mut body = Download::new(encoding, limit)?;
+
     let mut stream = response.bytes_stream();
-    let mut buffer = BytesMut::with_capacity(8192); // 8KB initial capacity
     let mut total_size = 0usize;
 
     while let Some(chunk) = stream.next().await {
-        let chunk = chunk
-            .map_err(|e| AppError::bad_request_with_source("error reading model stream", e))?;
+        let chunk = chunk.map_err(|e| {
+            AppError::bad_request_with_source(format!("error reading {} stream", what), e)
+        })?;
         total_size += chunk.len();
-        if total_size > MAX_MODEL_FILE_SIZE {
+        if total_size > limit {
             return Err(AppError::bad_request(format!(
-                "model file size exceeds limit during download (max: {} bytes)",
-                MAX_MODEL_FILE_SIZE
+                "{} file size exceeds limit during download (max: {} bytes)",
+                what, limit
             )));
         }
-        buffer.extend_from_slice(&chunk);
+        body.push(&chunk).map_err(|e| e.with_context(what))?;
     }
 
-    let bytes = buffer.freeze();
+    Ok(Bytes::from(
+        body.finish().map_err(|e| e.with_context(what))?,
+    ))
+}
 
-    let format = format
-        .or_else(|| model::Format::from_magic_bytes(&bytes))
-        .ok_or_else(|| AppError::bad_request("unsupported model format"))?;
-    if !format.validate_bytes(&bytes) {
-        return Err(AppError::bad_request("invalid model file"));
+/// Fetch the material libraries an OBJ file refers to, stored next to it. Libraries
+/// that are missing or unreadable are skipped, the materials then have no colour.
+async fn fetch_libraries(
+    user_id: &models::user::UserId,
+    order_id: &str,
+    item_id: &str,
+    libraries: &[String],
+) -> Vec<Material> {
+    let client = reqwest::Client::new();
+    let mut materials = Vec::new();
+
+    for library in libraries.iter().take(MAX_MATERIAL_LIBRARIES) {
+        // only the file name, exporters like to write absolute paths
+        let name = library.rsplit(['/', '\\']).next().unwrap_or(library);
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+        {
+            log::warn!("skipping material library with invalid name: {}", library);
+            continue;
+        }
+
+        let url = model_url(user_id, order_id, item_id, name);
+        match download(&client, &url, "material library", MAX_MATERIAL_LIBRARY_SIZE).await {
+            Ok(bytes) => materials.extend(model::mtl::parse(&bytes)),
+            Err(_) => log::warn!("material library {} could not be fetched", name),
+        }
     }
 
-    let triangles = match format {
-        model::Format::STL => model::stl::STlParser::parse(&bytes),
-    }?;
+    materials
+}
 
-    let volume = calculate::volume(&triangles);
-    let volume = match payload.unit.as_str() {
-        "mm" => volume,
-        "cm" => volume / 1000.0,
-        "m" => volume / 1_000_000.0,
-        _ => volume,
-    };
+pub(crate) fn parse_model(
+    format: &model::Format,
+    bytes: &[u8],
+) -> Result<Vec<model::Triangle>, AppError> {
+    match format {
+        model::Format::STL => model::stl::STlParser::parse(bytes),
+        model::Format::OBJ => model::obj::ObjParser::parse(bytes),
+        model::Format::ThreeMF => model::threemf::ThreeMfParser::parse(bytes),
+        model::Format::AMF => model::amf::AmfParser::parse(bytes),
+        model::Format::PLY => model::ply::PlyParser::parse(bytes),
+        model::Format::GLTF => model::gltf::GltfParser::parse(bytes),
+        model::Format::OFF => model::off::OffParser::parse(bytes),
+        model::Format::ZIP => Err(AppError::bad_request(
+            "zip archives are only supported for volume calculation",
+        )),
+    }
+}
 
-    Ok((
-        StatusCode::OK,
-        [(header::CONTENT_TYPE, "application/json")],
-        Json(CalculateVolumeRes::new(triangles.len(), volume)),
-    ))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron() -> Loaded {
+        let v = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [0.0, 0.0, 10.0],
+        ];
+        Loaded {
+            triangles: [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]]
+                .iter()
+                .map(|face| model::Triangle::new(face.map(|i| v[i])))
+                .collect(),
+            materials: None,
+            items: Vec::new(),
+        }
+    }
+
+    fn payload(extra: serde_json::Value) -> CalculateVolumeReq {
+        let mut json = serde_json::json!({
+            "order_id": "01K9N559GM0BXKW00QX5T5F4FH",
+            "item_id": "01K9N559GM0BXKW00QX9NJ47AR",
+            "file_name": "model.stl",
+            "unit": "mm",
+        });
+        json.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn archive_entries_are_validated() {
+        use std::io::Write;
+        use zip::{ZipWriter, write::SimpleFileOptions};
+
+        let mut writer = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, bytes) in [
+            ("part.stl", model::stl::to_binary(&tetrahedron().triangles)),
+            ("broken.stl", b"solid nothing to see here".to_vec()),
+            ("notes.txt", b"print in red".to_vec()),
+        ] {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(&bytes).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let res =
+            serde_json::to_value(analyse_archive(&payload(serde_json::json!({})), &bytes).unwrap())
+                .unwrap();
+        let files = res["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[0]["result"]["volume"].as_f64().unwrap() > 0.0);
+        assert_eq!(files[1]["file_name"], "broken.stl");
+        assert_eq!(files[1]["error"], "invalid model file");
+        assert_eq!(res["skipped"][0], "notes.txt");
+    }
+
+    fn analysed(payload: &CalculateVolumeReq) -> serde_json::Value {
+        serde_json::to_value(analyse(payload, tetrahedron()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn fingerprint_ignores_the_transform() {
+        let plain = analysed(&payload(serde_json::json!({})));
+        let transformed = analysed(&payload(serde_json::json!({
+            "transform": {"scale": 1.5, "mirror": [true, false, false], "rotation": [0, 0, 90]},
+        })));
+        assert_eq!(plain["fingerprint"], transformed["fingerprint"]);
+
+        let volume = |res: &serde_json::Value| res["volume"].as_f64().unwrap();
+        assert!((volume(&transformed) / volume(&plain) - 3.375).abs() < 1e-3);
+    }
+
+    #[test]
+    fn transformed_coordinates_stay_finite() {
+        let mut model = tetrahedron();
+        for triangle in &mut model.triangles {
+            *triangle = model::Triangle::new(triangle.vertices().map(|v| v.map(|c| c * 1e35)));
+        }
+        let payload = payload(serde_json::json!({"transform": {"scale": 1000}}));
+        let error = analyse(&payload, model).unwrap_err().to_string();
+        assert!(
+            error.contains("vertex coordinates must be finite"),
+            "{error}"
+        );
+    }
 }
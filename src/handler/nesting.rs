@@ -0,0 +1,103 @@
+use crate::calculate::nesting::{self, Bed, Footprint};
+use crate::error::AppError;
+use crate::handler::model::{blocking, fetch_model, model_url, parse_model};
+use crate::models;
+use crate::models::mdl::{NestingReq, NestingRes, PlacementRes};
+use axum::Extension;
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use validator::Validate;
+
+/// models downloaded and parsed at the same time, each may take up to the file limit
+const CONCURRENT_DOWNLOADS: usize = 4;
+
+/// Lay out every copy of the items of an order onto printer build plates.
+///
+/// Each model is reduced to the convex hull of its footprint on the XY plane and
+/// packed onto plates of the given size, so batch quotes can account for shared
+/// plate time. Returns the number of plates needed and where each copy goes.
+///
+/// This endpoint requires authentication. The user's access token must be
+/// provided in the Authorization header as a Bearer token.
+#[utoipa::path(
+    post,
+    path = "/api/nesting",
+    tag = "Model Calculations",
+    request_body = NestingReq,
+    responses(
+        (status = 200, description = "Parts nested successfully", body = NestingRes),
+        (status = 400, description = "Bad Request (file too large, invalid format, part larger than the plate, validation error)", body = models::error::ResponseError),
+        (status = 404, description = "Model not found, or related error", body = models::error::ResponseError),
+        (status = 500, description = "Internal Server Error", body = models::error::ResponseError),
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn nest(
+    Extension(user_id): Extension<models::user::UserId>,
+    Json(payload): Json<NestingReq>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let urls: Vec<String> = payload
+        .items
+        .iter()
+        .map(|item| model_url(&user_id, &payload.order_id, &item.item_id, &item.file_name))
+        .collect();
+    let footprints: Vec<Footprint> = stream::iter(urls)
+        .map(|url| async move {
+            let (format, bytes) = fetch_model(&url).await?;
+            blocking(move || {
+                let triangles = parse_model(&format, &bytes)?;
+                Ok(Footprint::new(&triangles))
+            })
+            .await
+        })
+        .buffered(CONCURRENT_DOWNLOADS)
+        .try_collect()
+        .await?;
+
+    // one footprint per copy, remembering which item and copy it belongs to
+    let (copies, footprints): (Vec<(usize, u32)>, Vec<Footprint>) = payload
+        .items
+        .iter()
+        .zip(footprints)
+        .enumerate()
+        .flat_map(|(index, (item, footprint))| {
+            (0..item.quantity).map(move |copy| ((index, copy), footprint.clone()))
+        })
+        .unzip();
+
+    let bed = Bed {
+        width: payload.bed_width,
+        depth: payload.bed_depth,
+        spacing: payload.spacing,
+    };
+    let nesting = nesting::nest(&footprints, &bed)?;
+
+    let placements = nesting
+        .placements
+        .into_iter()
+        .map(|placement| {
+            let (index, copy) = copies[placement.part];
+            PlacementRes {
+                item_id: payload.items[index].item_id.clone(),
+                copy,
+                plate: placement.plate,
+                rotation: placement.rotation,
+                offset: placement.offset,
+            }
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(NestingRes::new(nesting.plates, placements)),
+    ))
+}
@@ -0,0 +1,79 @@
+use crate::calculate::decimate::{self, Target};
+use crate::error::AppError;
+use crate::handler::model::{blocking, fetch_model, model_url, parse_model};
+use crate::model::{mesh::IndexedMesh, stl};
+use crate::models;
+use crate::models::mdl::PreviewReq;
+use axum::Extension;
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use validator::Validate;
+
+/// Build a lightweight preview mesh of a 3D model file stored in S3.
+///
+/// The model is simplified with quadric error edge collapses until it has at most
+/// `target_triangles` triangles, or until the next collapse would move the surface
+/// further than `max_error`. The result is returned as a binary STL file that can
+/// be stored next to the original for the web viewer.
+///
+/// This endpoint requires authentication. The user's access token must be
+/// provided in the Authorization header as a Bearer token.
+#[utoipa::path(
+    post,
+    path = "/api/preview",
+    tag = "Model Calculations",
+    request_body = PreviewReq,
+    responses(
+        (status = 200, description = "Preview mesh as a binary STL file", content_type = "model/stl", body = Vec<u8>),
+        (status = 400, description = "Bad Request (file too large, invalid format, validation error)", body = models::error::ResponseError),
+        (status = 404, description = "Model not found, or related error", body = models::error::ResponseError),
+        (status = 500, description = "Internal Server Error", body = models::error::ResponseError),
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn preview(
+    Extension(user_id): Extension<models::user::UserId>,
+    Json(payload): Json<PreviewReq>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let url = model_url(
+        &user_id,
+        &payload.order_id,
+        &payload.item_id,
+        &payload.file_name,
+    );
+    let (format, bytes) = fetch_model(&url).await?;
+    let target = Target {
+        triangles: payload.target_triangles as usize,
+        max_error: payload.max_error.map(f64::from),
+    };
+    let preview = blocking(move || {
+        let triangles = parse_model(&format, &bytes)?;
+        let mesh = decimate::decimate(&IndexedMesh::from_triangles(&triangles), &target);
+        Ok(stl::to_binary(&mesh.to_triangles()))
+    })
+    .await?;
+
+    let file_name = payload
+        .file_name
+        .rsplit_once('.')
+        .map_or(payload.file_name.as_str(), |(stem, _)| stem);
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "model/stl".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.preview.stl\"", file_name),
+            ),
+        ],
+        preview,
+    ))
+}
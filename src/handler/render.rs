@@ -0,0 +1,233 @@
+use crate::calculate::{
+    self,
+    slice::{self, Grid},
+};
+use crate::error::AppError;
+use crate::handler::model::{blocking, fetch_model, model_url, parse_model};
+use crate::model;
+use crate::models;
+use crate::models::mdl::{CrossSectionReq, CrossSectionRes, SectionRes, ThumbnailReq};
+use crate::render::{
+    section,
+    thumbnail::{self, Camera},
+};
+use axum::Extension;
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use base64::{Engine as _, engine::general_purpose};
+use validator::Validate;
+
+/// Render a PNG thumbnail of a 3D model file stored in S3.
+///
+/// The model is rendered on the CPU with an orthographic camera orbiting its
+/// centre, `yaw` turns the camera around the vertical axis and `pitch` raises it
+/// above the build plate. The background is transparent.
+///
+/// This endpoint requires authentication. The user's access token must be
+/// provided in the Authorization header as a Bearer token.
+#[utoipa::path(
+    post,
+    path = "/api/thumbnail",
+    tag = "Model Calculations",
+    request_body = ThumbnailReq,
+    responses(
+        (status = 200, description = "Rendered thumbnail", content_type = "image/png", body = Vec<u8>),
+        (status = 400, description = "Bad Request (file too large, invalid format, validation error)", body = models::error::ResponseError),
+        (status = 404, description = "Model not found, or related error", body = models::error::ResponseError),
+        (status = 500, description = "Internal Server Error", body = models::error::ResponseError),
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn thumbnail(
+    Extension(user_id): Extension<models::user::UserId>,
+    Json(payload): Json<ThumbnailReq>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let url = model_url(
+        &user_id,
+        &payload.order_id,
+        &payload.item_id,
+        &payload.file_name,
+    );
+    let (format, bytes) = fetch_model(&url).await?;
+    let camera = Camera {
+        yaw: payload.yaw,
+        pitch: payload.pitch,
+        width: payload.width,
+        height: payload.height,
+    };
+    let png = blocking(move || {
+        let triangles = parse_model(&format, &bytes)?;
+        thumbnail::render(&triangles, &camera).to_png()
+    })
+    .await?;
+
+    Ok((StatusCode::OK, [(header::CONTENT_TYPE, "image/png")], png))
+}
+
+/// Render filled cross-sections of a 3D model file stored in S3.
+///
+/// Either a single section at height `z` or `count` evenly spaced sections through
+/// the whole model are cut, heights are measured in mm from the lowest point of
+/// the model. Every section of a request shares the same framing, PNG images are
+/// returned base64 encoded and SVG documents as plain text.
+///
+/// This endpoint requires authentication. The user's access token must be
+/// provided in the Authorization header as a Bearer token.
+#[utoipa::path(
+    post,
+    path = "/api/cross-section",
+    tag = "Model Calculations",
+    request_body = CrossSectionReq,
+    responses(
+        (status = 200, description = "Cross-sections rendered successfully", body = CrossSectionRes),
+        (status = 400, description = "Bad Request (file too large, invalid format, height outside the model, validation error)", body = models::error::ResponseError),
+        (status = 404, description = "Model not found, or related error", body = models::error::ResponseError),
+        (status = 500, description = "Internal Server Error", body = models::error::ResponseError),
+    ),
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn cross_section(
+    Extension(user_id): Extension<models::user::UserId>,
+    Json(payload): Json<CrossSectionReq>,
+) -> Result<impl IntoResponse, AppError> {
+    payload.validate()?;
+
+    let url = model_url(
+        &user_id,
+        &payload.order_id,
+        &payload.item_id,
+        &payload.file_name,
+    );
+    let (format, bytes) = fetch_model(&url).await?;
+    let content_type = match payload.format.as_str() {
+        "svg" => "image/svg+xml",
+        _ => "image/png",
+    };
+
+    let sections = blocking(move || {
+        let triangles = parse_model(&format, &bytes)?;
+        sections(&payload, &triangles)
+    })
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        Json(CrossSectionRes::new(content_type, sections)),
+    ))
+}
+
+/// every section `payload` asks for, framed alike
+fn sections(
+    payload: &CrossSectionReq,
+    triangles: &[model::Triangle],
+) -> Result<Vec<SectionRes>, AppError> {
+    // an empty model has no bounds to measure heights from
+    if triangles.is_empty() {
+        return Err(AppError::bad_request("the model has no triangles to cut"));
+    }
+
+    let (min, max) = calculate::bounds(triangles);
+    let height = max[2] - min[2];
+    let heights: Vec<f32> = match (payload.z, payload.count) {
+        (Some(z), _) if z > height => {
+            return Err(AppError::bad_request(format!(
+                "z is above the top of the model ({:.2} mm)",
+                height
+            )));
+        }
+        (Some(z), _) => vec![z],
+        (None, Some(count)) => (0..count)
+            .map(|i| (i as f32 + 0.5) * height / count as f32)
+            .collect(),
+        (None, None) => Vec::new(),
+    };
+
+    let extent = (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+    let grid = Grid::covering(
+        [min[0], min[1]],
+        [max[0], max[1]],
+        extent / (payload.size - 2) as f32,
+    );
+
+    let absolute: Vec<f32> = heights.iter().map(|z| min[2] + z).collect();
+    slice::slice(triangles, &absolute)
+        .into_iter()
+        .zip(heights)
+        .map(|(layer, z)| {
+            let data = match payload.format.as_str() {
+                "svg" => section::svg(&layer, &grid),
+                _ => general_purpose::STANDARD.encode(section::png(&layer, &grid).to_png()?),
+            };
+            Ok(SectionRes {
+                z,
+                area: layer.area(),
+                contours: layer.contours.len(),
+                data,
+            })
+        })
+        .collect::<Result<Vec<_>, AppError>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(extra: serde_json::Value) -> CrossSectionReq {
+        let mut json = serde_json::json!({
+            "order_id": "01K9N559GM0BXKW00QX5T5F4FH",
+            "item_id": "01K9N559GM0BXKW00QX9NJ47AR",
+            "file_name": "model.stl",
+        });
+        json.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn tetrahedron() -> Vec<model::Triangle> {
+        let v = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [0.0, 0.0, 10.0],
+        ];
+        [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]]
+            .iter()
+            .map(|face| model::Triangle::new(face.map(|i| v[i])))
+            .collect()
+    }
+
+    #[test]
+    fn heights_outside_the_model() {
+        let error = sections(&payload(serde_json::json!({"z": 1.0})), &[])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no triangles"), "{error}");
+        assert!(!error.contains("inf"), "{error}");
+
+        let error = sections(&payload(serde_json::json!({"z": 12.0})), &tetrahedron())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("above the top of the model (10.00 mm)"),
+            "{error}"
+        );
+
+        let cut = sections(
+            &payload(serde_json::json!({"z": 5.0, "format": "svg"})),
+            &tetrahedron(),
+        )
+        .unwrap();
+        assert_eq!(cut.len(), 1);
+    }
+}
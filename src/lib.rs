@@ -6,4 +6,5 @@ pub mod handler;
 pub mod middleware;
 pub mod model;
 pub mod models;
+pub mod render;
 pub mod util;
@@ -50,6 +50,30 @@ async fn main() -> anyhow::Result<()> {
                 slicer_rs::middleware::auth::access_token,
             )),
         )
+        .route(
+            "/nesting",
+            post(handler::nesting::nest).route_layer(middleware::from_fn(
+                slicer_rs::middleware::auth::access_token,
+            )),
+        )
+        .route(
+            "/preview",
+            post(handler::preview::preview).route_layer(middleware::from_fn(
+                slicer_rs::middleware::auth::access_token,
+            )),
+        )
+        .route(
+            "/thumbnail",
+            post(handler::render::thumbnail).route_layer(middleware::from_fn(
+                slicer_rs::middleware::auth::access_token,
+            )),
+        )
+        .route(
+            "/cross-section",
+            post(handler::render::cross_section).route_layer(middleware::from_fn(
+                slicer_rs::middleware::auth::access_token,
+            )),
+        )
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
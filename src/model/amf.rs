@@ -0,0 +1,481 @@
+// Additive Manufacturing File format
+//
+// AMF is XML, often zipped with a single `.amf` entry. Every object has one vertex
+// list shared by its volumes, and every volume is a list of triangles made of one
+// material. Constellations place objects, and other constellations, with an offset
+// and a rotation; when a file has none every object is printed where it is. Curved
+// triangles (`edges`) are read as flat ones and textures are ignored.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+};
+
+use zip::ZipArchive;
+
+use crate::{
+    error::AppError,
+    model::{
+        self, MAX_DEPTH, MAX_TRIANGLES, MeshParser, Triangle,
+        archive::{self, Budget},
+        material::{Material, Materials},
+        mesh::{self, IndexedMesh},
+        transform::Transform,
+    },
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Amf {
+    /// every placed object, in millimetres
+    pub triangles: Vec<Triangle>,
+    /// `None` when no volume names a material
+    pub materials: Option<Materials>,
+}
+
+#[derive(Debug, Default)]
+struct Object {
+    mesh: IndexedMesh,
+    /// material id of every face
+    materials: Vec<Option<String>>,
+}
+
+#[derive(Debug, Clone)]
+struct Instance {
+    /// object or constellation id
+    id: String,
+    transform: Transform,
+}
+
+#[derive(Debug, Default)]
+struct Document {
+    objects: HashMap<String, Object>,
+    /// constellations in file order
+    constellations: Vec<(String, Vec<Instance>)>,
+    materials: HashMap<String, Material>,
+}
+
+pub fn validate_bytes(bytes: &[u8]) -> bool {
+    detect(bytes)
+}
+
+/// whether the bytes are an AMF document, or a zip holding one
+pub fn detect(bytes: &[u8]) -> bool {
+    if archive::is_zip(bytes) {
+        return ZipArchive::new(Cursor::new(bytes)).is_ok_and(|archive| entry(&archive).is_some());
+    }
+    let preview = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
+    preview
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('<')
+        && preview.contains("<amf")
+}
+
+/// the AMF document inside a zip
+fn entry(archive: &ZipArchive<Cursor<&[u8]>>) -> Option<String> {
+    archive
+        .file_names()
+        .find(|name| name.to_lowercase().ends_with(".amf"))
+        .map(str::to_string)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Amf, AppError> {
+    parse_with_budget(bytes, &mut Budget::default())
+}
+
+/// Parse a file whose zipped document draws on `budget`, like one nested in an
+/// archive.
+pub fn parse_with_budget(bytes: &[u8], budget: &mut Budget) -> Result<Amf, AppError> {
+    if archive::is_zip(bytes) {
+        let mut archive = archive::open(bytes).map_err(|e| e.with_context("invalid AMF file:"))?;
+        let name = entry(&archive).ok_or_else(|| error("the zip holds no AMF document"))?;
+        let content = archive::read_entry(&mut archive, &name, budget)?;
+        return parse_document(&content);
+    }
+    parse_document(bytes)
+}
+
+fn parse_document(bytes: &[u8]) -> Result<Amf, AppError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|e| AppError::bad_request_with_source("invalid AMF file: not UTF-8", e))?;
+    let xml = roxmltree::Document::parse(text.trim_start_matches('\u{feff}'))
+        .map_err(|e| AppError::bad_request_with_source("invalid AMF file: malformed XML", e))?;
+
+    let root = xml.root_element();
+    if !root.has_tag_name("amf") {
+        return Err(error("the document has no amf element"));
+    }
+    let unit = match root.attribute("unit").unwrap_or("millimeter") {
+        "micron" | "micrometer" => 0.001,
+        "millimeter" => 1.0,
+        "inch" => 25.4,
+        "feet" => 304.8,
+        "meter" => 1000.0,
+        other => return Err(error(format!("unknown unit {}", other))),
+    };
+
+    let mut document = Document::default();
+    for node in root.children().filter(|node| node.is_element()) {
+        let id = || {
+            node.attribute("id")
+                .map(str::to_string)
+                .ok_or_else(|| error(format!("{} needs an id", node.tag_name().name())))
+        };
+        match node.tag_name().name() {
+            "object" => {
+                document.objects.insert(id()?, parse_object(node, unit)?);
+            }
+            "material" => {
+                let name = node
+                    .children()
+                    .find(|n| n.has_tag_name("metadata") && n.attribute("type") == Some("name"))
+                    .and_then(|n| n.text())
+                    .map(|name| name.trim().to_string())
+                    .unwrap_or_else(|| format!("material {}", id().unwrap_or_default()));
+                // channels may be formulas, those materials are left without a colour
+                let color = node
+                    .children()
+                    .find(|n| n.has_tag_name("color"))
+                    .and_then(|color| {
+                        Some([
+                            value(color, "r").ok()?,
+                            value(color, "g").ok()?,
+                            value(color, "b").ok()?,
+                            optional(color, "a").ok()?.unwrap_or(1.0),
+                        ])
+                    });
+                document.materials.insert(id()?, Material { name, color });
+            }
+            "constellation" => {
+                let instances = node
+                    .children()
+                    .filter(|n| n.has_tag_name("instance"))
+                    .map(|instance| parse_instance(instance, unit))
+                    .collect::<Result<_, _>>()?;
+                document.constellations.push((id()?, instances));
+            }
+            _ => {}
+        }
+    }
+
+    let mut amf = Amf::default();
+    let mut materials = Materials::default();
+    let mut placements = 0;
+
+    // constellations no other constellation uses are the build, without any every
+    // object stands where it is
+    let nested: HashSet<&str> = document
+        .constellations
+        .iter()
+        .flat_map(|(_, instances)| instances.iter().map(|instance| instance.id.as_str()))
+        .collect();
+    let roots: Vec<Instance> = if document.constellations.is_empty() {
+        let mut ids: Vec<&String> = document.objects.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .map(|id| Instance {
+                id: id.clone(),
+                transform: Transform::identity(),
+            })
+            .collect()
+    } else {
+        document
+            .constellations
+            .iter()
+            .filter(|(id, _)| !nested.contains(id.as_str()))
+            .map(|(id, _)| Instance {
+                id: id.clone(),
+                transform: Transform::identity(),
+            })
+            .collect()
+    };
+
+    for instance in roots.iter() {
+        place(
+            &document,
+            instance,
+            0,
+            &mut placements,
+            &mut amf.triangles,
+            &mut materials,
+        )?;
+    }
+
+    if amf.triangles.is_empty() {
+        return Err(error("the file contains no triangles"));
+    }
+    if materials.assignment.iter().any(Option::is_some) {
+        amf.materials = Some(materials);
+    }
+
+    Ok(amf)
+}
+
+/// append an object, or every instance of a constellation, moved by its transform
+fn place(
+    document: &Document,
+    instance: &Instance,
+    depth: usize,
+    placements: &mut usize,
+    triangles: &mut Vec<Triangle>,
+    materials: &mut Materials,
+) -> Result<(), AppError> {
+    *placements += 1;
+    if depth > MAX_DEPTH {
+        return Err(error("constellations are nested too deeply"));
+    }
+    model::check_placements(*placements)?;
+
+    if let Some(object) = document.objects.get(&instance.id) {
+        let start = triangles.len();
+        if start + object.mesh.faces.len() > MAX_TRIANGLES as usize {
+            return Err(AppError::bad_request(format!(
+                "too many triangles, the limit is {}",
+                MAX_TRIANGLES
+            )));
+        }
+        triangles.extend(object.mesh.to_triangles());
+        instance.transform.apply(&mut triangles[start..]);
+        // offsets are not checked when they are read, a huge one overflows here
+        mesh::check_triangles(&triangles[start..])?;
+
+        for id in object.materials.iter() {
+            let material = id.as_ref().and_then(|id| document.materials.get(id));
+            let assigned = material.map(|material| {
+                let assigned = materials.index(&material.name);
+                materials.materials[assigned as usize].color = material.color;
+                assigned
+            });
+            materials.assignment.push(assigned);
+        }
+        return Ok(());
+    }
+
+    let (_, instances) = document
+        .constellations
+        .iter()
+        .find(|(id, _)| *id == instance.id)
+        .ok_or_else(|| error(format!("instance of missing object {}", instance.id)))?;
+    for child in instances.iter() {
+        let child = Instance {
+            id: child.id.clone(),
+            transform: child.transform.compose(instance.transform),
+        };
+        place(
+            document,
+            &child,
+            depth + 1,
+            placements,
+            triangles,
+            materials,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn parse_object(node: roxmltree::Node, unit: f32) -> Result<Object, AppError> {
+    let mut object = Object::default();
+    let Some(mesh) = node.children().find(|n| n.has_tag_name("mesh")) else {
+        return Ok(object);
+    };
+
+    if let Some(vertices) = mesh.children().find(|n| n.has_tag_name("vertices")) {
+        for vertex in vertices.children().filter(|n| n.has_tag_name("vertex")) {
+            let coordinates = vertex
+                .children()
+                .find(|n| n.has_tag_name("coordinates"))
+                .ok_or_else(|| error("vertex without coordinates"))?;
+            let position: [f32; 3] = [
+                value(coordinates, "x")?,
+                value(coordinates, "y")?,
+                value(coordinates, "z")?,
+            ];
+            object.mesh.add_vertex(position.map(|c| c * unit))?;
+        }
+    }
+
+    let count = object.mesh.vertices.len() as u32;
+    for volume in mesh.children().filter(|n| n.has_tag_name("volume")) {
+        let material = volume.attribute("materialid").map(str::to_string);
+        for triangle in volume.children().filter(|n| n.has_tag_name("triangle")) {
+            let face: [u32; 3] = [
+                value(triangle, "v1")?,
+                value(triangle, "v2")?,
+                value(triangle, "v3")?,
+            ];
+            if face.iter().any(|&v| v >= count) {
+                return Err(error("triangle refers to a missing vertex"));
+            }
+            object.mesh.faces.push(face);
+            object.materials.push(material.clone());
+        }
+        if object.mesh.faces.len() > MAX_TRIANGLES as usize {
+            return Err(AppError::bad_request(format!(
+                "too many triangles, the limit is {}",
+                MAX_TRIANGLES
+            )));
+        }
+    }
+
+    Ok(object)
+}
+
+/// An instance is moved by `deltax`.. and rotated by `rx`.. in degrees, the rotation
+/// applying first.
+fn parse_instance(node: roxmltree::Node, unit: f32) -> Result<Instance, AppError> {
+    let id = node
+        .attribute("objectid")
+        .ok_or_else(|| error("instance needs an objectid"))?;
+    let offset = ["deltax", "deltay", "deltaz"]
+        .map(|name| optional(node, name).map(|v| v.unwrap_or(0.0) * unit));
+    let rotation = ["rx", "ry", "rz"].map(|name| optional(node, name).map(|v| v.unwrap_or(0.0)));
+    let [dx, dy, dz] = offset;
+    let [rx, ry, rz] = rotation;
+
+    Ok(Instance {
+        id: id.to_string(),
+        transform: Transform::identity()
+            .rotate([rx?, ry?, rz?])
+            .translate([dx?, dy?, dz?]),
+    })
+}
+
+/// number held by the child element `name`
+fn value<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, AppError> {
+    optional(node, name)?.ok_or_else(|| {
+        error(format!(
+            "{} needs a {} element",
+            node.tag_name().name(),
+            name
+        ))
+    })
+}
+
+fn optional<T: std::str::FromStr>(
+    node: roxmltree::Node,
+    name: &str,
+) -> Result<Option<T>, AppError> {
+    let Some(child) = node.children().find(|n| n.has_tag_name(name)) else {
+        return Ok(None);
+    };
+    child
+        .text()
+        .and_then(|text| text.trim().parse().ok())
+        .map(Some)
+        .ok_or_else(|| error(format!("invalid {} value", name)))
+}
+
+fn error(message: impl std::fmt::Display) -> AppError {
+    AppError::bad_request(format!("invalid AMF file: {}", message))
+}
+
+pub struct AmfParser;
+
+impl MeshParser for AmfParser {
+    fn parse(bytes: &[u8]) -> Result<Vec<Triangle>, AppError> {
+        Ok(parse(bytes)?.triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+    use crate::calculate;
+
+    /// tetrahedron in inches with its top face in a second, red volume
+    fn document(constellations: &str) -> String {
+        let vertices: String = [[0, 0, 0], [1, 0, 0], [0, 1, 0], [0, 0, 1]]
+            .iter()
+            .map(|[x, y, z]| {
+                format!(
+                    "<vertex><coordinates><x>{}</x><y>{}</y><z>{}</z></coordinates></vertex>",
+                    x, y, z
+                )
+            })
+            .collect();
+        let triangle = |[a, b, c]: [u32; 3]| {
+            format!(
+                "<triangle><v1>{}</v1><v2>{}</v2><v3>{}</v3></triangle>",
+                a, b, c
+            )
+        };
+        format!(
+            r#"<?xml version="1.0"?><amf unit="inch"><material id="1"><metadata type="name">Red</metadata><color><r>1</r><g>0</g><b>0</b></color></material><object id="0"><mesh><vertices>{}</vertices><volume>{}{}{}</volume><volume materialid="1">{}</volume></mesh></object>{}</amf>"#,
+            vertices,
+            triangle([0, 2, 1]),
+            triangle([0, 1, 3]),
+            triangle([0, 3, 2]),
+            triangle([1, 2, 3]),
+            constellations
+        )
+    }
+
+    fn zipped(document: &str) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("part.amf", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(document.as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    const TETRAHEDRON: f32 = 25.4 * 25.4 * 25.4 / 6.0;
+
+    #[test]
+    fn volumes_and_materials() {
+        let amf = parse(document("").as_bytes()).unwrap();
+        assert!((calculate::volume(&amf.triangles) / TETRAHEDRON - 1.0).abs() < 1e-4);
+        let materials = amf.materials.unwrap();
+        assert!(
+            materials
+                .materials
+                .iter()
+                .any(|material| material.name == "Red")
+        );
+        assert_eq!(materials.assignment.len(), 4);
+    }
+
+    #[test]
+    fn constellations() {
+        let constellation = r#"<constellation id="10"><instance objectid="0"/><instance objectid="0"><deltax>5</deltax><rz>90</rz></instance></constellation>"#;
+        let amf = parse(document(constellation).as_bytes()).unwrap();
+        assert_eq!(amf.triangles.len(), 8);
+        assert!((calculate::volume(&amf.triangles) / TETRAHEDRON - 2.0).abs() < 1e-4);
+
+        let cycle = r#"<constellation id="10"><instance objectid="11"/></constellation><constellation id="11"><instance objectid="10"/></constellation><constellation id="12"><instance objectid="10"/></constellation>"#;
+        let error = parse(document(cycle).as_bytes()).unwrap_err().to_string();
+        assert!(error.contains("nested too deeply"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_non_finite_coordinates() {
+        let placed = r#"<constellation id="10"><instance objectid="0"><deltax>1e38</deltax></instance></constellation>"#;
+        for document in [
+            document("").replacen("<x>1</x>", "<x>NaN</x>", 1),
+            document("").replacen("<z>1</z>", "<z>inf</z>", 1),
+            // inches overflow once they are turned into millimetres
+            document("").replacen("<x>1</x>", "<x>1e38</x>", 1),
+            document(placed),
+        ] {
+            let error = parse(document.as_bytes()).unwrap_err().to_string();
+            assert!(error.contains("finite"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn zipped_document() {
+        let bytes = zipped(&document(""));
+        assert!(detect(&bytes));
+        assert_eq!(parse(&bytes).unwrap().triangles.len(), 4);
+
+        // padding that compresses far beyond the ratio limit
+        let bomb = zipped(&format!("{}{}", document(""), " ".repeat(4 * 1024 * 1024)));
+        let error = parse(&bomb).unwrap_err().to_string();
+        assert!(error.contains("compressed too much"), "{}", error);
+    }
+}
@@ -0,0 +1,329 @@
+// Zip packages
+//
+// Several model formats come zipped, and customers upload whole projects as one
+// archive. Entries are decompressed into memory, the sizes written in the archive
+// are not trusted. Against zip bombs every entry is bounded in compression ratio,
+// and everything read from one archive shares a budget of decompressed bytes.
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Seek},
+};
+
+use zip::{ZipArchive, read::ZipFile};
+
+use crate::{
+    error::AppError,
+    model::{Format, compression},
+};
+
+/// most entries an archive may list
+pub const MAX_ARCHIVE_ENTRIES: usize = 1000;
+/// most models analysed from one uploaded archive
+pub const MAX_ARCHIVE_MODELS: usize = 50;
+/// most bytes decompressed from one archive, all entries together
+pub const MAX_ARCHIVE_SIZE: u64 = 256 * 1024 * 1024;
+/// largest ratio of decompressed to compressed size of an entry
+pub const MAX_COMPRESSION_RATIO: u64 = 100;
+
+/// decompressed bytes an archive may still take up
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    remaining: u64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            remaining: MAX_ARCHIVE_SIZE,
+        }
+    }
+}
+
+impl Budget {
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// fail when `bytes` are more than the budget has left
+    pub fn check(&self, bytes: u64) -> Result<(), AppError> {
+        if bytes > self.remaining {
+            return Err(AppError::bad_request(format!(
+                "the archive is too large once decompressed, the limit is {} bytes",
+                MAX_ARCHIVE_SIZE
+            )));
+        }
+        Ok(())
+    }
+
+    /// take `bytes` from the budget, failing when it has not that much left
+    pub fn spend(&mut self, bytes: u64) -> Result<(), AppError> {
+        self.check(bytes)?;
+        self.remaining -= bytes;
+        Ok(())
+    }
+}
+
+/// a model found in an uploaded archive
+pub struct Entry {
+    /// path inside the archive
+    pub name: String,
+    pub format: Format,
+    pub bytes: Vec<u8>,
+}
+
+/// MTL files of an archive by their path inside it
+#[derive(Default)]
+pub struct Libraries(HashMap<String, Vec<u8>>);
+
+impl Libraries {
+    /// The library an OBJ file at `model` refers to as `reference`, relative to the
+    /// directory of the model. Absolute paths, as some exporters write them, fall
+    /// back to the file name next to the model.
+    pub fn get(&self, model: &str, reference: &str) -> Option<&[u8]> {
+        let model = model.replace('\\', "/");
+        let directory = model.rfind('/').map_or("", |i| &model[..=i]);
+        let reference = reference.replace('\\', "/");
+        let file_name = reference.rsplit('/').next().unwrap_or(&reference);
+        [
+            format!("{}{}", directory, reference),
+            format!("{}{}", directory, file_name),
+        ]
+        .iter()
+        .filter_map(|path| normalize(path))
+        .find_map(|path| self.0.get(&path))
+        .map(Vec::as_slice)
+    }
+}
+
+/// path inside the archive with `.` and `..` resolved, `None` when it leaves it
+fn normalize(path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[derive(Default)]
+pub struct Archive {
+    pub models: Vec<Entry>,
+    pub libraries: Libraries,
+    /// files that are not models of a supported format
+    pub skipped: Vec<String>,
+    /// what is left for the parts of 3MF and zipped AMF models
+    pub budget: Budget,
+}
+
+pub fn is_zip(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
+
+/// open a zip held in memory, refusing archives with too many entries
+pub fn open(bytes: &[u8]) -> Result<ZipArchive<Cursor<&[u8]>>, AppError> {
+    let archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| AppError::bad_request_with_source("not a zip archive", e))?;
+    if archive.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(AppError::bad_request(format!(
+            "the archive has too many entries, the limit is {}",
+            MAX_ARCHIVE_ENTRIES
+        )));
+    }
+    Ok(archive)
+}
+
+/// decompress the entry `name`, paying for it out of `budget`
+pub fn read_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+    budget: &mut Budget,
+) -> Result<Vec<u8>, AppError> {
+    let file = archive
+        .by_name(name)
+        .map_err(|e| AppError::bad_request_with_source(format!("{} is missing", name), e))?;
+    read(file, name, budget)
+}
+
+/// The sizes written in the archive are checked first, the read then stops at the
+/// same limits in case they lie.
+fn read<R: Read>(
+    mut file: ZipFile<'_, R>,
+    name: &str,
+    budget: &mut Budget,
+) -> Result<Vec<u8>, AppError> {
+    let ratio_limit = file.compressed_size().saturating_mul(MAX_COMPRESSION_RATIO);
+    let too_compressed = || {
+        AppError::bad_request(format!(
+            "{} is compressed too much, the limit is {}:1",
+            name, MAX_COMPRESSION_RATIO
+        ))
+    };
+    if file.size() > ratio_limit {
+        return Err(too_compressed());
+    }
+    budget.check(file.size())?;
+
+    let limit = ratio_limit.min(budget.remaining());
+    let mut bytes = Vec::new();
+    file.by_ref()
+        .take(limit + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| AppError::bad_request_with_source(format!("{} is corrupt", name), e))?;
+    if bytes.len() as u64 > ratio_limit {
+        return Err(too_compressed());
+    }
+    budget.spend(bytes.len() as u64)?;
+
+    Ok(bytes)
+}
+
+/// Every model of an uploaded archive, with the material libraries OBJ files may
+/// refer to. Directories, hidden files and macOS metadata are left out, and zip
+/// archives inside are not opened. Packages, 3MF and zipped AMF, are opened when
+/// they are parsed, within the budget this leaves.
+pub fn models(bytes: &[u8]) -> Result<Archive, AppError> {
+    let mut archive = open(bytes).map_err(|e| e.with_context("invalid zip archive:"))?;
+
+    let mut models = Archive::default();
+    for index in 0..archive.len() {
+        let name = {
+            let file = archive
+                .by_index_raw(index)
+                .map_err(|e| AppError::bad_request_with_source("invalid zip archive", e))?;
+            if file.is_dir() {
+                continue;
+            }
+            file.name().to_string()
+        };
+        let file_name = name.rsplit(['/', '\\']).next().unwrap_or(&name).to_string();
+        if name.starts_with("__MACOSX/") || file_name.starts_with('.') {
+            continue;
+        }
+
+        let format = Format::from_url(&name).filter(|format| !matches!(format, Format::ZIP));
+        let is_library = file_name.to_lowercase().ends_with(".mtl");
+        if format.is_none() && !is_library {
+            models.skipped.push(name);
+            continue;
+        }
+        if format.is_some() && models.models.len() == MAX_ARCHIVE_MODELS {
+            return Err(AppError::bad_request(format!(
+                "the archive has too many models, the limit is {}",
+                MAX_ARCHIVE_MODELS
+            )));
+        }
+
+        let file = archive
+            .by_index(index)
+            .map_err(|e| AppError::bad_request_with_source(format!("{} is corrupt", name), e))?;
+        let mut content = read(file, &name, &mut models.budget)?;
+        // models may be compressed on their own inside the archive, bounded in ratio
+        // the same as the archive entries
+        if compression::Encoding::from_magic_bytes(&content).is_some() {
+            let ratio_limit = (content.len() as u64).saturating_mul(MAX_COMPRESSION_RATIO);
+            let limit = ratio_limit.min(models.budget.remaining());
+            content = compression::decompress(content, limit as usize)
+                .map_err(|e| e.with_context(&name))?;
+            models.budget.spend(content.len() as u64)?;
+        }
+
+        match format {
+            Some(format) => models.models.push(Entry {
+                name,
+                format,
+                bytes: content,
+            }),
+            None => {
+                if let Some(path) = normalize(&name) {
+                    models.libraries.0.insert(path, content);
+                }
+            }
+        }
+    }
+
+    if models.models.is_empty() {
+        return Err(AppError::bad_request(
+            "the archive contains no supported model files",
+        ));
+    }
+
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    /// zip of `files`, stored without compression of its own
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for (name, bytes) in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(bytes).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn compressed_models_are_bounded_in_ratio() {
+        let model = b"solid part\nendsolid part\n".repeat(40);
+        let bytes = zip(&[("part.stl.gz", &gzip(&model))]);
+        let archive = models(&bytes).unwrap();
+        assert_eq!(archive.models[0].bytes, model);
+
+        // a few kilobytes inflating to megabytes, well within the archive budget
+        let bomb = gzip(&vec![b' '; 4 * 1024 * 1024]);
+        assert!((bomb.len() as u64) * MAX_COMPRESSION_RATIO < 4 * 1024 * 1024);
+        let error = models(&zip(&[("bomb.stl.gz", &bomb)]))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("exceeds limit once decompressed"), "{error}");
+    }
+
+    #[test]
+    fn libraries_next_to_their_model() {
+        let bytes = zip(&[
+            ("a/part.obj", b"mtllib part.mtl\nv 0 0 0\n"),
+            ("a/part.mtl", b"newmtl red"),
+            ("b/part.mtl", b"newmtl blue"),
+            ("b\\shared\\common.mtl", b"newmtl green"),
+        ]);
+        let archive = models(&bytes).unwrap();
+        let libraries = &archive.libraries;
+        assert_eq!(
+            libraries.get("a/part.obj", "part.mtl"),
+            Some(&b"newmtl red"[..])
+        );
+        assert_eq!(
+            libraries.get("b/x.obj", "part.mtl"),
+            Some(&b"newmtl blue"[..])
+        );
+        assert_eq!(
+            libraries.get("b/x/y.obj", "../shared/common.mtl"),
+            Some(&b"newmtl green"[..])
+        );
+        assert_eq!(
+            libraries.get("a/part.obj", "C:\\Users\\me\\part.mtl"),
+            Some(&b"newmtl red"[..])
+        );
+        assert_eq!(libraries.get("part.obj", "part.mtl"), None);
+    }
+}
@@ -0,0 +1,335 @@
+// Compressed uploads
+//
+// Models may be uploaded gzip or zstd compressed, told apart by the
+// `Content-Encoding` of the object or by their magic bytes. They are decompressed
+// while they download, and the size limit applies to the decompressed model.
+
+use std::io::{self, Write};
+
+use flate2::write::MultiGzDecoder;
+use zstd::stream::{raw, zio};
+
+use crate::error::AppError;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    pub fn from_content_encoding(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Some(Self::Gzip)
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+/// file name without a `.gz` or `.zst` extension, which leaves the one of the model
+pub fn strip_extension(name: &str) -> &str {
+    let lower = name.to_lowercase();
+    [".gz", ".zst", ".zstd"]
+        .iter()
+        .find(|extension| lower.ends_with(*extension))
+        .map_or(name, |extension| &name[..name.len() - extension.len()])
+}
+
+/// output of a decoder, refusing to grow beyond the limit
+struct Limited {
+    bytes: Vec<u8>,
+    limit: usize,
+    exceeded: bool,
+}
+
+impl Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.bytes.len() + buf.len() > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::other("decompressed size exceeds the limit"));
+        }
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+enum State {
+    /// not enough bytes yet to recognise compression
+    Undecided(Vec<u8>),
+    Plain(Vec<u8>),
+    Gzip(Box<MultiGzDecoder<Limited>>),
+    Zstd(Box<zio::Writer<Limited, raw::Decoder<'static>>>),
+}
+
+/// Collects a download chunk by chunk, decompressing it on the fly when the
+/// encoding is given or the first bytes reveal it.
+pub struct Download {
+    state: State,
+    limit: usize,
+}
+
+impl Download {
+    pub fn new(encoding: Option<Encoding>, limit: usize) -> Result<Self, AppError> {
+        let mut download = Self {
+            state: State::Undecided(Vec::with_capacity(8192)),
+            limit,
+        };
+        if let Some(encoding) = encoding {
+            download.start(encoding)?;
+        }
+        Ok(download)
+    }
+
+    fn start(&mut self, encoding: Encoding) -> Result<(), AppError> {
+        let output = Limited {
+            bytes: Vec::with_capacity(8192),
+            limit: self.limit,
+            exceeded: false,
+        };
+        self.state = match encoding {
+            Encoding::Gzip => State::Gzip(Box::new(MultiGzDecoder::new(output))),
+            Encoding::Zstd => State::Zstd(Box::new(zio::Writer::new(
+                output,
+                raw::Decoder::new().map_err(|e| {
+                    AppError::bad_request_with_source("failed to start zstd decoder", e)
+                })?,
+            ))),
+        };
+        Ok(())
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), AppError> {
+        if let State::Undecided(buffer) = &mut self.state {
+            buffer.extend_from_slice(chunk);
+            if buffer.len() < ZSTD_MAGIC.len() {
+                return Ok(());
+            }
+            let buffer = std::mem::take(buffer);
+            match Encoding::from_magic_bytes(&buffer) {
+                Some(encoding) => {
+                    self.start(encoding)?;
+                    return self.push(&buffer);
+                }
+                None => {
+                    self.state = State::Plain(buffer);
+                    return self.check_plain();
+                }
+            }
+        }
+
+        let result = match &mut self.state {
+            State::Plain(bytes) => {
+                bytes.extend_from_slice(chunk);
+                return self.check_plain();
+            }
+            State::Gzip(decoder) => decoder.write_all(chunk),
+            State::Zstd(decoder) => decoder.write_all(chunk),
+            State::Undecided(_) => unreachable!("the encoding is decided above"),
+        };
+        result.map_err(|e| self.error(e))
+    }
+
+    /// the decompressed download, failing when the compressed data ends early
+    pub fn finish(self) -> Result<Vec<u8>, AppError> {
+        match self.state {
+            State::Undecided(bytes) | State::Plain(bytes) => Ok(bytes),
+            State::Gzip(mut decoder) => {
+                decoder
+                    .try_finish()
+                    .map_err(|e| error(decoder.get_ref(), e))?;
+                Ok(std::mem::take(&mut decoder.get_mut().bytes))
+            }
+            State::Zstd(mut decoder) => {
+                decoder.finish().map_err(|e| error(decoder.writer(), e))?;
+                Ok(decoder.into_inner().0.bytes)
+            }
+        }
+    }
+
+    fn check_plain(&self) -> Result<(), AppError> {
+        match &self.state {
+            State::Plain(bytes) if bytes.len() > self.limit => Err(too_large(self.limit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn error(&self, e: io::Error) -> AppError {
+        match &self.state {
+            State::Gzip(decoder) => error(decoder.get_ref(), e),
+            State::Zstd(decoder) => error(decoder.writer(), e),
+            _ => AppError::bad_request_with_source("file is not valid compressed data", e),
+        }
+    }
+}
+
+fn error(output: &Limited, e: io::Error) -> AppError {
+    if output.exceeded {
+        too_large(output.limit)
+    } else {
+        AppError::bad_request_with_source("file is not valid compressed data", e)
+    }
+}
+
+fn too_large(limit: usize) -> AppError {
+    AppError::bad_request(format!(
+        "file size exceeds limit once decompressed (max: {} bytes)",
+        limit
+    ))
+}
+
+/// decompress bytes that are already in memory, when they are compressed
+pub fn decompress(bytes: Vec<u8>, limit: usize) -> Result<Vec<u8>, AppError> {
+    let Some(encoding) = Encoding::from_magic_bytes(&bytes) else {
+        return Ok(bytes);
+    };
+    let mut download = Download::new(Some(encoding), limit)?;
+    download.push(&bytes)?;
+    download.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> Vec<u8> {
+        (0..2000)
+            .flat_map(|i| format!("v {i} {} {}\n", i * 2, i * 3).into_bytes())
+            .collect()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zstd(bytes: &[u8]) -> Vec<u8> {
+        zstd::encode_all(bytes, 3).unwrap()
+    }
+
+    /// feed the download in chunks of `size` bytes
+    fn download(
+        bytes: &[u8],
+        encoding: Option<Encoding>,
+        size: usize,
+        limit: usize,
+    ) -> Result<Vec<u8>, AppError> {
+        let mut download = Download::new(encoding, limit)?;
+        for chunk in bytes.chunks(size) {
+            download.push(chunk)?;
+        }
+        download.finish()
+    }
+
+    #[test]
+    fn content_encodings() {
+        assert_eq!(
+            Encoding::from_content_encoding("gzip"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            Encoding::from_content_encoding(" X-Gzip "),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            Encoding::from_content_encoding("ZSTD"),
+            Some(Encoding::Zstd)
+        );
+        assert_eq!(Encoding::from_content_encoding("identity"), None);
+        assert_eq!(Encoding::from_content_encoding("br"), None);
+    }
+
+    #[test]
+    fn magic_bytes_split_across_chunks() {
+        let model = model();
+        for compressed in [gzip(&model), zstd(&model)] {
+            for size in [1, 3, 4096] {
+                assert_eq!(
+                    download(&compressed, None, size, usize::MAX).unwrap(),
+                    model
+                );
+            }
+        }
+        assert_eq!(download(&model, None, 1, usize::MAX).unwrap(), model);
+        assert_eq!(download(b"abc", None, 1, usize::MAX).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn declared_encoding() {
+        let model = model();
+        let gzipped = gzip(&model);
+        assert_eq!(
+            download(&gzipped, Some(Encoding::Gzip), 5, usize::MAX).unwrap(),
+            model
+        );
+        assert_eq!(
+            download(&zstd(&model), Some(Encoding::Zstd), 5, usize::MAX).unwrap(),
+            model
+        );
+
+        // the declared encoding wins over the magic bytes
+        let error = download(&gzipped, Some(Encoding::Zstd), 5, usize::MAX).unwrap_err();
+        assert!(error.to_string().contains("not valid compressed data"));
+    }
+
+    #[test]
+    fn truncated_streams() {
+        let model = model();
+        for compressed in [gzip(&model), zstd(&model)] {
+            let truncated = &compressed[..compressed.len() - 8];
+            let error = download(truncated, None, 7, usize::MAX).unwrap_err();
+            assert!(error.to_string().contains("not valid compressed data"));
+        }
+    }
+
+    #[test]
+    fn decompressed_size_limit() {
+        let model = model();
+        for compressed in [gzip(&model), zstd(&model)] {
+            // only the decompressed model is over the limit
+            let limit = model.len() / 2;
+            assert!(compressed.len() < limit);
+            let error = download(&compressed, None, 64, limit).unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .contains("exceeds limit once decompressed"),
+                "{error}"
+            );
+            assert_eq!(download(&compressed, None, 64, model.len()).unwrap(), model);
+        }
+
+        let error = download(&model, None, 64, 1000).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("exceeds limit once decompressed")
+        );
+    }
+
+    #[test]
+    fn in_memory() {
+        let model = model();
+        assert_eq!(decompress(gzip(&model), usize::MAX).unwrap(), model);
+        assert_eq!(decompress(zstd(&model), usize::MAX).unwrap(), model);
+        assert_eq!(decompress(model.clone(), 10).unwrap(), model);
+    }
+}
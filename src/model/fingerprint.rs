@@ -0,0 +1,64 @@
+// Geometric fingerprint
+//
+// Every triangle is quantised and rotated so its smallest vertex comes first, which
+// keeps the winding but removes the dependence on where the file started each
+// facet. The per-triangle hashes are then summed, making the result independent of
+// facet order as well. Optionally the model is moved so its bounding box starts at
+// the origin first, which makes the fingerprint independent of translation too.
+
+use rayon::prelude::*;
+
+use crate::{calculate, model::Triangle};
+
+/// grid the coordinates are snapped to before hashing (mm)
+const QUANTUM: f64 = 1e-3;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 128-bit fingerprint of the geometry as a 32 character hex string
+pub fn fingerprint(triangles: &[Triangle], translation_invariant: bool) -> String {
+    let offset = if translation_invariant && !triangles.is_empty() {
+        calculate::bounds(triangles).0
+    } else {
+        [0.0; 3]
+    };
+
+    let (low, high) = triangles
+        .par_iter()
+        .map(|triangle| {
+            let vertices = triangle.vertices().map(|v| {
+                std::array::from_fn::<i64, 3, _>(|axis| {
+                    ((v[axis] as f64 - offset[axis] as f64) / QUANTUM).round() as i64
+                })
+            });
+            let first = (0..3).min_by_key(|&i| vertices[i]).unwrap_or(0);
+
+            let mut hash = FNV_OFFSET;
+            for k in 0..3 {
+                for c in vertices[(first + k) % 3] {
+                    for byte in c.to_le_bytes() {
+                        hash ^= byte as u64;
+                        hash = hash.wrapping_mul(FNV_PRIME);
+                    }
+                }
+            }
+
+            // spread each triangle hash over two words before summing
+            (mix(hash), mix(hash ^ 0x9e37_79b9_7f4a_7c15))
+        })
+        .reduce(
+            || (0u64, 0u64),
+            |a, b| (a.0.wrapping_add(b.0), a.1.wrapping_add(b.1)),
+        );
+
+    let low = low ^ mix(triangles.len() as u64);
+    format!("{:016x}{:016x}", high, low)
+}
+
+/// splitmix64 finaliser
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
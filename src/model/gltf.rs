@@ -0,0 +1,464 @@
+// glTF 2.0, as JSON (`.gltf`) or binary (`.glb`)
+//
+// The nodes of the default scene are walked down from the roots with their
+// transforms, and the triangle primitives of their meshes are read through their
+// accessors, sparse ones included. Buffers must be embedded, either as the binary
+// chunk of a GLB or as base64 data URIs; files referring to other files can not be
+// resolved. glTF positions are in metres and converted to millimetres. Required
+// extensions that change how geometry is stored, like Draco or meshopt compression,
+// are rejected.
+
+use ::gltf::{
+    Gltf, Semantic,
+    accessor::{Accessor, DataType, Dimensions},
+    buffer::{Source, View},
+    mesh::Mode,
+};
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::{
+    error::AppError,
+    model::{
+        self, MAX_DEPTH, MAX_TRIANGLES, MeshParser, Triangle,
+        material::{Material, Materials},
+        mesh,
+        transform::Transform,
+    },
+};
+
+const METRE: f32 = 1000.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct GltfModel {
+    /// triangles of every mesh in the scene, in millimetres
+    pub triangles: Vec<Triangle>,
+    /// `None` when no primitive has a material
+    pub materials: Option<Materials>,
+}
+
+#[derive(Deserialize)]
+struct Required {
+    #[serde(rename = "extensionsRequired", default)]
+    extensions_required: Vec<String>,
+}
+
+pub fn validate_bytes(bytes: &[u8]) -> bool {
+    detect(bytes)
+}
+
+/// whether the bytes are a GLB container or glTF JSON
+pub fn detect(bytes: &[u8]) -> bool {
+    if bytes.starts_with(b"glTF") {
+        return true;
+    }
+    let preview = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
+    preview
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('{')
+        && preview.contains("\"asset\"")
+}
+
+pub fn parse(bytes: &[u8]) -> Result<GltfModel, AppError> {
+    check_extensions(bytes)?;
+    let gltf = Gltf::from_slice(bytes)
+        .map_err(|e| AppError::bad_request_with_source("invalid glTF file", e))?;
+
+    let buffers = gltf
+        .buffers()
+        .map(|buffer| {
+            let data = match buffer.source() {
+                Source::Bin => gltf.blob.clone(),
+                Source::Uri(uri) => Some(decode_uri(uri)?),
+            }
+            .ok_or_else(|| error("the binary chunk is missing"))?;
+            if data.len() < buffer.length() {
+                return Err(error("a buffer is shorter than declared"));
+            }
+            Ok(data)
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let mut model = GltfModel::default();
+    let mut materials = Materials::default();
+
+    let roots: Vec<::gltf::Node> = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let children: HashSet<usize> = gltf
+                .nodes()
+                .flat_map(|node| node.children().map(|child| child.index()))
+                .collect();
+            gltf.nodes()
+                .filter(|node| !children.contains(&node.index()))
+                .collect()
+        }
+    };
+
+    // triangles of every primitive of a mesh, decoded once however often it is placed
+    let mut decoded: HashMap<usize, Vec<Vec<Triangle>>> = HashMap::new();
+
+    let units = Transform::identity().scale([METRE; 3]);
+    let mut stack: Vec<(::gltf::Node, Transform, usize)> =
+        roots.into_iter().map(|node| (node, units, 0)).collect();
+    let mut placements = 0;
+    while let Some((node, parent, depth)) = stack.pop() {
+        placements += 1;
+        if depth > MAX_DEPTH {
+            return Err(error("nodes are nested too deeply"));
+        }
+        // nodes may wrongly be shared, which multiplies the parts placed
+        model::check_placements(placements)?;
+        let matrix = node.transform().matrix();
+        let transform = Transform::from_rows(std::array::from_fn(|row| {
+            std::array::from_fn(|column| matrix[column][row])
+        }))
+        .compose(parent);
+
+        if let Some(mesh) = node.mesh() {
+            let primitives = match decoded.entry(mesh.index()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(
+                    mesh.primitives()
+                        .map(|primitive| read_primitive(&primitive, &buffers))
+                        .collect::<Result<_, AppError>>()?,
+                ),
+            };
+            for (primitive, triangles) in mesh.primitives().zip(primitives.iter()) {
+                let start = model.triangles.len();
+                if start + triangles.len() > MAX_TRIANGLES as usize {
+                    return Err(too_many_triangles());
+                }
+                model.triangles.extend_from_slice(triangles);
+                transform.apply(&mut model.triangles[start..]);
+                // node matrices are not checked, large ones overflow here
+                mesh::check_triangles(&model.triangles[start..])?;
+
+                let material = primitive.material();
+                let assigned = material.index().map(|index| {
+                    let name = material
+                        .name()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("material {}", index));
+                    let assigned = materials.index(&name);
+                    materials.materials[assigned as usize] = Material {
+                        name,
+                        color: Some(material.pbr_metallic_roughness().base_color_factor()),
+                    };
+                    assigned
+                });
+                materials.assignment.resize(model.triangles.len(), assigned);
+            }
+        }
+        stack.extend(node.children().map(|child| (child, transform, depth + 1)));
+    }
+
+    if model.triangles.is_empty() {
+        return Err(error("the scene contains no triangles"));
+    }
+    if materials.assignment.iter().any(Option::is_some) {
+        model.materials = Some(materials);
+    }
+
+    Ok(model)
+}
+
+/// reject required extensions before the document is read, with a clear reason
+fn check_extensions(bytes: &[u8]) -> Result<(), AppError> {
+    let json = if bytes.starts_with(b"glTF") {
+        ::gltf::binary::Glb::from_slice(bytes)
+            .map_err(|e| AppError::bad_request_with_source("invalid GLB file", e))?
+            .json
+    } else {
+        bytes.into()
+    };
+    let required: Required = serde_json::from_slice(&json)
+        .map_err(|e| AppError::bad_request_with_source("invalid glTF file", e))?;
+
+    for extension in required.extensions_required.iter() {
+        let reason = match extension.as_str() {
+            "KHR_draco_mesh_compression" => "Draco compressed meshes are not supported",
+            "EXT_meshopt_compression" | "KHR_meshopt_compression" => {
+                "meshopt compressed buffers are not supported"
+            }
+            "KHR_mesh_quantization" => "quantized meshes are not supported",
+            other if ::gltf::json::extensions::ENABLED_EXTENSIONS.contains(&other) => continue,
+            other => {
+                return Err(AppError::bad_request(format!(
+                    "unsupported glTF file: required extension {} is not supported",
+                    other
+                )));
+            }
+        };
+        return Err(AppError::bad_request(format!(
+            "unsupported glTF file: {}",
+            reason
+        )));
+    }
+
+    Ok(())
+}
+
+/// contents of a base64 `data:` URI
+fn decode_uri(uri: &str) -> Result<Vec<u8>, AppError> {
+    let Some(data) = uri.strip_prefix("data:") else {
+        return Err(AppError::bad_request(
+            "unsupported glTF file: buffers in separate files can not be read, upload a GLB",
+        ));
+    };
+    let (_, payload) = data
+        .split_once(";base64,")
+        .ok_or_else(|| error("data URIs must be base64"))?;
+    base64::engine::general_purpose::STANDARD
+        .decode(payload)
+        .map_err(|e| AppError::bad_request_with_source("invalid glTF file: corrupt data URI", e))
+}
+
+/// triangles of a primitive in its mesh's own coordinates
+fn read_primitive(
+    primitive: &::gltf::Primitive,
+    buffers: &[Vec<u8>],
+) -> Result<Vec<Triangle>, AppError> {
+    let mode = primitive.mode();
+    if !matches!(
+        mode,
+        Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+    ) {
+        // points and lines have no volume
+        return Ok(Vec::new());
+    }
+
+    let positions = primitive
+        .get(&Semantic::Positions)
+        .ok_or_else(|| error("a primitive has no positions"))?;
+    if positions.data_type() != DataType::F32 || positions.dimensions() != Dimensions::Vec3 {
+        return Err(error("positions must be float vectors"));
+    }
+    check_accessor(&positions)?;
+    if let Some(indices) = primitive.indices() {
+        if !matches!(
+            indices.data_type(),
+            DataType::U8 | DataType::U16 | DataType::U32
+        ) || indices.dimensions() != Dimensions::Scalar
+        {
+            return Err(error("indices must be unsigned integers"));
+        }
+        check_accessor(&indices)?;
+    }
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+    let vertices: Vec<[f32; 3]> = reader
+        .read_positions()
+        .ok_or_else(|| error("a primitive has no positions"))?
+        .collect();
+    vertices.iter().try_for_each(mesh::check_position)?;
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+    if indices.iter().any(|&i| i as usize >= vertices.len()) {
+        return Err(error("an index refers to a missing vertex"));
+    }
+
+    let count = match mode {
+        Mode::Triangles => indices.len() / 3,
+        _ => indices.len().saturating_sub(2),
+    };
+    if count > MAX_TRIANGLES as usize {
+        return Err(too_many_triangles());
+    }
+
+    let corner = |i: u32| vertices[i as usize];
+    let mut triangles = Vec::with_capacity(count);
+    for k in 0..count {
+        let [a, b, c] = match mode {
+            Mode::Triangles => [indices[3 * k], indices[3 * k + 1], indices[3 * k + 2]],
+            // every other triangle of a strip is wound the other way
+            Mode::TriangleStrip if k % 2 == 1 => [indices[k + 1], indices[k], indices[k + 2]],
+            Mode::TriangleStrip => [indices[k], indices[k + 1], indices[k + 2]],
+            _ => [indices[0], indices[k + 1], indices[k + 2]],
+        };
+        triangles.push(Triangle::new([corner(a), corner(b), corner(c)]));
+    }
+
+    Ok(triangles)
+}
+
+fn too_many_triangles() -> AppError {
+    AppError::bad_request(format!(
+        "too many triangles, the limit is {}",
+        MAX_TRIANGLES
+    ))
+}
+
+/// Whether the data of an accessor lies within its buffer views, the reader
+/// assumes it does.
+fn check_accessor(accessor: &Accessor) -> Result<(), AppError> {
+    let size = accessor.size();
+    if let Some(view) = accessor.view()
+        && !fits(&view, accessor.offset(), accessor.count(), size)
+    {
+        return Err(error("an accessor reaches past its buffer view"));
+    }
+
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_size = match indices.index_type() {
+            ::gltf::accessor::sparse::IndexType::U8 => 1,
+            ::gltf::accessor::sparse::IndexType::U16 => 2,
+            ::gltf::accessor::sparse::IndexType::U32 => 4,
+        };
+        let values = sparse.values();
+        if !fits(
+            &indices.view(),
+            indices.offset(),
+            sparse.count(),
+            index_size,
+        ) || !fits(&values.view(), values.offset(), sparse.count(), size)
+        {
+            return Err(error("a sparse accessor reaches past its buffer view"));
+        }
+    }
+
+    Ok(())
+}
+
+/// whether `count` elements of `size` bytes from `offset` fit in the view
+fn fits(view: &View, offset: usize, count: usize, size: usize) -> bool {
+    let stride = view.stride().unwrap_or(size).max(size);
+    let end = count.checked_sub(1).map_or(Some(0), |last| {
+        last.checked_mul(stride)?
+            .checked_add(offset)?
+            .checked_add(size)
+    });
+    end.is_some_and(|end| end <= view.length())
+        && view.offset() + view.length() <= view.buffer().length()
+}
+
+fn error(message: impl std::fmt::Display) -> AppError {
+    AppError::bad_request(format!("invalid glTF file: {}", message))
+}
+
+pub struct GltfParser;
+
+impl MeshParser for GltfParser {
+    fn parse(bytes: &[u8]) -> Result<Vec<Triangle>, AppError> {
+        Ok(parse(bytes)?.triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate;
+
+    /// tetrahedron with 1 cm edges, positions followed by u16 indices
+    fn buffer() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for position in [
+            [0.0f32, 0.0, 0.0],
+            [0.01, 0.0, 0.0],
+            [0.0, 0.01, 0.0],
+            [0.0, 0.0, 0.01],
+        ] {
+            position.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        }
+        for index in [0u16, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3] {
+            bytes.extend(index.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn json(scene: &str) -> String {
+        format!(
+            r#"{{"asset":{{"version":"2.0"}},{}"meshes":[{{"primitives":[{{"attributes":{{"POSITION":0}},"indices":1}}]}}],"accessors":[{{"bufferView":0,"componentType":5126,"count":4,"type":"VEC3","min":[0,0,0],"max":[0.01,0.01,0.01]}},{{"bufferView":1,"componentType":5123,"count":12,"type":"SCALAR"}}],"bufferViews":[{{"buffer":0,"byteLength":48}},{{"buffer":0,"byteOffset":48,"byteLength":24}}],"buffers":[{{"byteLength":72}}]}}"#,
+            scene
+        )
+    }
+
+    fn glb(json: &str) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let buffer = buffer();
+
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(((12 + 8 + json.len() + 8 + buffer.len()) as u32).to_le_bytes());
+        bytes.extend((json.len() as u32).to_le_bytes());
+        bytes.extend(b"JSON");
+        bytes.extend(json);
+        bytes.extend((buffer.len() as u32).to_le_bytes());
+        bytes.extend(b"BIN\0");
+        bytes.extend(buffer);
+        bytes
+    }
+
+    const TETRAHEDRON: f32 = 1000.0 / 6.0;
+
+    #[test]
+    fn mesh_placed_twice() {
+        let bytes = glb(&json(
+            r#""scene":0,"scenes":[{"nodes":[0,1]}],"nodes":[{"mesh":0},{"mesh":0,"translation":[0.02,0,0]}],"#,
+        ));
+        assert!(detect(&bytes));
+        let model = parse(&bytes).unwrap();
+        assert_eq!(model.triangles.len(), 8);
+        assert!((calculate::volume(&model.triangles) / TETRAHEDRON - 2.0).abs() < 1e-4);
+        let (min, max) = calculate::bounds(&model.triangles);
+        assert!((max[0] - min[0] - 30.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn roots_without_a_scene() {
+        let bytes = glb(&json(
+            r#""nodes":[{"mesh":0,"children":[1]},{"mesh":0,"scale":[2,2,2]}],"#,
+        ));
+        let model = parse(&bytes).unwrap();
+        assert!((calculate::volume(&model.triangles) / TETRAHEDRON - 9.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn embedded_json_buffer() {
+        let data = base64::engine::general_purpose::STANDARD.encode(buffer());
+        let json = json(r#""nodes":[{"mesh":0}],"#).replace(
+            r#"{"byteLength":72}"#,
+            &format!(
+                r#"{{"byteLength":72,"uri":"data:application/octet-stream;base64,{}"}}"#,
+                data
+            ),
+        );
+        assert!(detect(json.as_bytes()));
+        assert_eq!(parse(json.as_bytes()).unwrap().triangles.len(), 4);
+    }
+
+    #[test]
+    fn truncated_glb() {
+        let bytes = glb(&json(r#""nodes":[{"mesh":0}],"#));
+        for end in [bytes.len() - 10, 40, 12] {
+            assert!(parse(&bytes[..end]).is_err(), "cut at {}", end);
+        }
+    }
+
+    #[test]
+    fn rejects_non_finite_positions() {
+        let mut bytes = glb(&json(r#""nodes":[{"mesh":0}],"#));
+        let start = bytes.len() - 72;
+        bytes[start..start + 4].copy_from_slice(&f32::NAN.to_le_bytes());
+        let scaled = glb(&json(r#""nodes":[{"mesh":0,"scale":[1e38,1,1]}],"#));
+        for bytes in [bytes, scaled] {
+            let error = parse(&bytes).unwrap_err().to_string();
+            assert!(error.contains("finite"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_draco() {
+        let json =
+            json(r#""nodes":[{"mesh":0}],"extensionsRequired":["KHR_draco_mesh_compression"],"#);
+        let error = parse(&glb(&json)).unwrap_err().to_string();
+        assert!(error.contains("Draco"), "{}", error);
+    }
+}
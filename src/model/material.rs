@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+/// distinct colours reported as materials of their own
+const MAX_COLORS: usize = 64;
+/// material of models with more colours than that
+const FULL_COLOR: &str = "full_color";
+
+/// a named material a model refers to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// RGBA, 0 to 1
+    pub color: Option<[f32; 4]>,
+}
+
+impl Material {
+    /// `#RRGGBB`, or `#RRGGBBAA` when not opaque, to name materials by their colour
+    pub fn color_name(rgba: [u8; 4]) -> String {
+        let [r, g, b, a] = rgba;
+        if a == u8::MAX {
+            format!("#{:02X}{:02X}{:02X}", r, g, b)
+        } else {
+            format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+        }
+    }
+}
+
+/// Which material every triangle of a model is made of.
+#[derive(Debug, Clone, Default)]
+pub struct Materials {
+    pub materials: Vec<Material>,
+    /// index into `materials` for every triangle, `None` where the file left it open
+    pub assignment: Vec<Option<u32>>,
+    /// index of every material by name
+    indices: HashMap<String, u32>,
+}
+
+impl Materials {
+    /// index of the material called `name`, added if it is not known yet
+    pub fn index(&mut self, name: &str) -> u32 {
+        if let Some(&index) = self.indices.get(name) {
+            return index;
+        }
+        let index = self.materials.len() as u32;
+        self.materials.push(Material {
+            name: name.to_string(),
+            color: None,
+        });
+        self.indices.insert(name.to_string(), index);
+        index
+    }
+
+    /// Past `MAX_COLORS` materials named by their colour, those are merged into a
+    /// single full colour material the way `from_colors` does. Named materials are
+    /// kept.
+    pub fn limit_colors(&mut self) {
+        let is_color = |material: &Material| material.name.starts_with('#');
+        if self.materials.iter().filter(|m| is_color(m)).count() <= MAX_COLORS {
+            return;
+        }
+
+        let mut merged = Materials::default();
+        let remap: Vec<u32> = self
+            .materials
+            .iter()
+            .map(|material| {
+                if is_color(material) {
+                    return merged.index(FULL_COLOR);
+                }
+                let index = merged.index(&material.name);
+                merged.materials[index as usize].color = material.color;
+                index
+            })
+            .collect();
+        merged.assignment = self
+            .assignment
+            .iter()
+            .map(|assigned| assigned.map(|index| remap[index as usize]))
+            .collect();
+        *self = merged;
+    }
+
+    /// One material per distinct face colour, named by its hex code. Scans often use
+    /// thousands of colours, past `MAX_COLORS` the model is a single full colour
+    /// material instead.
+    pub fn from_colors(faces: &[[u8; 4]]) -> Self {
+        let mut materials = Materials::default();
+        let mut distinct = faces.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if distinct.len() > MAX_COLORS {
+            let index = materials.index(FULL_COLOR);
+            materials.assignment = vec![Some(index); faces.len()];
+            return materials;
+        }
+
+        for &rgba in faces {
+            let index = materials.index(&Material::color_name(rgba));
+            materials.materials[index as usize].color = Some(rgba.map(|c| c as f32 / 255.0));
+            materials.assignment.push(Some(index));
+        }
+        materials
+    }
+}
+
+/// average colour of the corners of a face, `colors` holding RGBA from 0 to 1
+pub fn face_color(colors: &[[f32; 4]], face: &[u32; 3]) -> [u8; 4] {
+    std::array::from_fn(|channel| {
+        let sum: f32 = face.iter().map(|&v| colors[v as usize][channel]).sum();
+        (sum / 3.0 * 255.0).round().clamp(0.0, 255.0) as u8
+    })
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::AppError,
+    model::{MAX_TRIANGLES, MAX_VERTICES, Triangle},
+};
+
+/// Shared-vertex representation of a triangle soup.
+///
+/// Vertices with bit-identical coordinates are welded together, so faces refer to
+/// their corners by index and connectivity between faces is available.
+#[derive(Debug, Clone, Default)]
+pub struct IndexedMesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub faces: Vec<[u32; 3]>,
+}
+
+impl IndexedMesh {
+    pub fn from_triangles(triangles: &[Triangle]) -> Self {
+        let mut lookup: HashMap<[u32; 3], u32> = HashMap::with_capacity(triangles.len() / 2);
+        let mut vertices = Vec::with_capacity(triangles.len() / 2);
+
+        let faces = triangles
+            .iter()
+            .map(|triangle| {
+                triangle.vertices.map(|v| {
+                    // +0.0 folds -0.0 into 0.0 so both weld into the same vertex
+                    let key = v.map(|c| (c + 0.0).to_bits());
+                    *lookup.entry(key).or_insert_with(|| {
+                        vertices.push(v);
+                        (vertices.len() - 1) as u32
+                    })
+                })
+            })
+            .collect();
+
+        Self { vertices, faces }
+    }
+
+    /// Add a vertex of the parsed file. All parsers of indexed formats add their
+    /// vertices through here, which refuses more than `MAX_VERTICES` of them and
+    /// coordinates that are not finite.
+    pub fn add_vertex(&mut self, position: [f32; 3]) -> Result<(), AppError> {
+        if self.vertices.len() >= MAX_VERTICES as usize {
+            return Err(AppError::bad_request(format!(
+                "too many vertices, the limit is {}",
+                MAX_VERTICES
+            )));
+        }
+        check_position(&position)?;
+        self.vertices.push(position);
+        Ok(())
+    }
+
+    /// Add a polygon of the parsed file, split into a fan around its first corner.
+    /// All parsers of indexed formats add their faces through here, so the same
+    /// polygons give the same triangles whatever the format.
+    pub fn add_polygon(&mut self, polygon: &[u32]) -> Result<(), AppError> {
+        if polygon.len() < 3 {
+            return Err(AppError::bad_request("face needs at least three vertices"));
+        }
+        if polygon.iter().any(|&v| v as usize >= self.vertices.len()) {
+            return Err(AppError::bad_request("face refers to a missing vertex"));
+        }
+        if self.faces.len() + polygon.len() - 2 > MAX_TRIANGLES as usize {
+            return Err(AppError::bad_request(format!(
+                "too many triangles, the limit is {}",
+                MAX_TRIANGLES
+            )));
+        }
+
+        for k in 1..polygon.len() - 1 {
+            self.faces.push([polygon[0], polygon[k], polygon[k + 1]]);
+        }
+        Ok(())
+    }
+
+    /// Label every face with the connected shell it belongs to, faces sharing a
+    /// vertex are connected. Returns the number of shells and a label per face.
+    pub fn shells(&self) -> (usize, Vec<u32>) {
+        let mut parent: Vec<u32> = (0..self.vertices.len() as u32).collect();
+        fn root(parent: &mut [u32], mut i: u32) -> u32 {
+            while parent[i as usize] != i {
+                parent[i as usize] = parent[parent[i as usize] as usize];
+                i = parent[i as usize];
+            }
+            i
+        }
+
+        for face in self.faces.iter() {
+            let a = root(&mut parent, face[0]);
+            for &v in &face[1..] {
+                let b = root(&mut parent, v);
+                parent[b as usize] = a;
+            }
+        }
+
+        let mut labels: HashMap<u32, u32> = HashMap::new();
+        let shells = self
+            .faces
+            .iter()
+            .map(|face| {
+                let r = root(&mut parent, face[0]);
+                let next = labels.len() as u32;
+                *labels.entry(r).or_insert(next)
+            })
+            .collect();
+
+        (labels.len(), shells)
+    }
+
+    pub fn to_triangles(&self) -> Vec<Triangle> {
+        self.faces
+            .iter()
+            .map(|face| Triangle {
+                vertices: face.map(|i| self.vertices[i as usize]),
+            })
+            .collect()
+    }
+}
+
+/// Fail on a NaN or infinite coordinate, a single one spoils the bounds, the slices
+/// and the fingerprint of the whole model.
+pub fn check_position(position: &[f32; 3]) -> Result<(), AppError> {
+    if position.iter().any(|c| !c.is_finite()) {
+        return Err(AppError::bad_request("vertex coordinates must be finite"));
+    }
+    Ok(())
+}
+
+/// [`check_position`] for every corner, for triangles that were not added through
+/// an [`IndexedMesh`] or were moved since
+pub fn check_triangles(triangles: &[Triangle]) -> Result<(), AppError> {
+    triangles
+        .iter()
+        .flat_map(|triangle| triangle.vertices.iter())
+        .try_for_each(check_position)
+}
@@ -1,10 +1,40 @@
+pub mod amf;
+pub mod archive;
+pub mod compression;
+pub mod fingerprint;
+pub mod gltf;
+pub mod material;
+pub mod mesh;
+pub mod mtl;
+pub mod obj;
+pub mod off;
+pub mod ply;
 pub mod stl;
+pub mod threemf;
+pub mod transform;
 
 use nalgebra::Vector3;
 
 use crate::error::AppError;
 
 pub const MAX_TRIANGLES: u32 = 10_000_000;
+/// vertices a model may list, whether faces use them or not
+pub const MAX_VERTICES: u32 = 3 * MAX_TRIANGLES;
+/// levels of components, constellations or nodes an assembly may nest
+pub const MAX_DEPTH: usize = 32;
+/// parts an assembly may place, objects shared by several parents counted every time
+pub const MAX_PLACEMENTS: usize = 100_000;
+
+/// fails once an assembly has placed more than [`MAX_PLACEMENTS`] parts
+pub fn check_placements(placements: usize) -> Result<(), AppError> {
+    if placements > MAX_PLACEMENTS {
+        return Err(AppError::bad_request(format!(
+            "too many placed parts, the limit is {}",
+            MAX_PLACEMENTS
+        )));
+    }
+    Ok(())
+}
 
 #[derive(Debug, Clone)]
 pub struct Triangle {
@@ -12,6 +42,14 @@ pub struct Triangle {
 }
 
 impl Triangle {
+    pub fn new(vertices: [[f32; 3]; 3]) -> Self {
+        Self { vertices }
+    }
+
+    pub fn vertices(&self) -> &[[f32; 3]; 3] {
+        &self.vertices
+    }
+
     pub fn signed_volume(&self) -> f32 {
         let a = Vector3::from(self.vertices[0]);
         let b = Vector3::from(self.vertices[1]);
@@ -28,6 +66,14 @@ pub trait MeshParser {
 
 pub enum Format {
     STL,
+    OBJ,
+    ThreeMF,
+    AMF,
+    PLY,
+    GLTF,
+    OFF,
+    /// a zip of several models, only their volumes are calculated
+    ZIP,
 }
 
 impl Format {
@@ -37,15 +83,52 @@ impl Format {
             || content_type.contains("model/stl")
         {
             Some(Format::STL)
+        } else if content_type.contains("model/obj") {
+            Some(Format::OBJ)
+        } else if content_type.contains("model/3mf")
+            || content_type.contains("application/vnd.ms-package.3dmanufacturing-3dmodel+xml")
+        {
+            Some(Format::ThreeMF)
+        } else if content_type.contains("application/x-amf") || content_type.contains("model/amf") {
+            Some(Format::AMF)
+        } else if content_type.contains("application/ply")
+            || content_type.contains("application/x-ply")
+            || content_type.contains("model/ply")
+        {
+            Some(Format::PLY)
+        } else if content_type.contains("model/gltf-binary")
+            || content_type.contains("model/gltf+json")
+        {
+            Some(Format::GLTF)
+        } else if content_type.contains("model/off")
+            || content_type.contains("application/off")
+            || content_type.contains("application/x-off")
+        {
+            Some(Format::OFF)
         } else {
             None
         }
     }
 
     pub fn from_url(url: &str) -> Option<Self> {
-        let url = url.to_lowercase();
+        // compressed uploads keep the extension of the model before their own
+        let url = compression::strip_extension(url).to_lowercase();
         if url.ends_with(".stl") {
             Some(Format::STL)
+        } else if url.ends_with(".obj") {
+            Some(Format::OBJ)
+        } else if url.ends_with(".3mf") {
+            Some(Format::ThreeMF)
+        } else if url.ends_with(".amf") {
+            Some(Format::AMF)
+        } else if url.ends_with(".ply") {
+            Some(Format::PLY)
+        } else if url.ends_with(".glb") || url.ends_with(".gltf") {
+            Some(Format::GLTF)
+        } else if url.ends_with(".off") {
+            Some(Format::OFF)
+        } else if url.ends_with(".zip") {
+            Some(Format::ZIP)
         } else {
             None
         }
@@ -56,20 +139,52 @@ impl Format {
             return None;
         }
 
+        // 3MF file detection, zip packages can not be mistaken for the text formats
+        if threemf::detect(bytes) {
+            return Some(Format::ThreeMF);
+        }
+
+        // AMF file detection, plain or zipped XML
+        if amf::detect(bytes) {
+            return Some(Format::AMF);
+        }
+
+        // any other zip is an archive of models
+        if archive::is_zip(bytes) {
+            return Some(Format::ZIP);
+        }
+
+        // PLY file detection
+        if ply::detect(bytes) {
+            return Some(Format::PLY);
+        }
+
+        // OFF file detection
+        if off::detect(bytes) {
+            return Some(Format::OFF);
+        }
+
+        // glTF file detection, binary container or JSON
+        if gltf::detect(bytes) {
+            return Some(Format::GLTF);
+        }
+
         // STL file detection
         // binary STL files detection
-        let traingle_count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]);
-        if traingle_count > 0
-            && traingle_count <= MAX_TRIANGLES
-            && let Some(expected_size) = 84usize.checked_add(traingle_count as usize * 50)
-            && bytes.len() >= expected_size
-            && bytes.len() <= expected_size + 80
-        {
-            return Some(Format::STL);
+        if bytes.len() >= 84 {
+            let traingle_count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]);
+            if traingle_count > 0
+                && traingle_count <= MAX_TRIANGLES
+                && let Some(expected_size) = 84usize.checked_add(traingle_count as usize * 50)
+                && bytes.len() >= expected_size
+                && bytes.len() <= expected_size + 80
+            {
+                return Some(Format::STL);
+            }
         }
 
         // ASCII STL files detection
-        if &bytes[..5] == b"solid" {
+        if bytes.starts_with(b"solid") {
             let preview = &bytes[..bytes.len().min(4096)];
             if let Ok(content) = std::str::from_utf8(preview)
                 && content.contains("facet")
@@ -79,18 +194,37 @@ impl Format {
             }
         }
 
+        // OBJ file detection
+        if obj::detect(bytes) {
+            return Some(Format::OBJ);
+        }
+
         None
     }
 
     pub fn validate_bytes(&self, bytes: &[u8]) -> bool {
         match self {
             Self::STL => stl::validate_bytes(bytes),
+            Self::OBJ => obj::validate_bytes(bytes),
+            Self::ThreeMF => threemf::validate_bytes(bytes),
+            Self::AMF => amf::validate_bytes(bytes),
+            Self::PLY => ply::validate_bytes(bytes),
+            Self::GLTF => gltf::validate_bytes(bytes),
+            Self::OFF => off::validate_bytes(bytes),
+            Self::ZIP => archive::is_zip(bytes),
         }
     }
 
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::STL => "stl",
+            Self::OBJ => "obj",
+            Self::ThreeMF => "3mf",
+            Self::AMF => "amf",
+            Self::PLY => "ply",
+            Self::GLTF => "gltf",
+            Self::OFF => "off",
+            Self::ZIP => "zip",
         }
     }
 }
@@ -0,0 +1,42 @@
+// Wavefront material library
+//
+// Only the material names and their diffuse colour (`Kd`) with the dissolve (`d`)
+// or transparency (`Tr`) as alpha are kept, everything about textures and shading
+// is irrelevant for printing.
+
+use crate::model::material::Material;
+
+pub fn parse(bytes: &[u8]) -> Vec<Material> {
+    let content = String::from_utf8_lossy(bytes);
+    let mut materials: Vec<Material> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut fields = line.split_whitespace();
+        let keyword = fields.next();
+        let values: Vec<f32> = fields.clone().filter_map(|f| f.parse().ok()).collect();
+
+        match (keyword, materials.last_mut()) {
+            (Some("newmtl"), _) => materials.push(Material {
+                name: fields.collect::<Vec<_>>().join(" "),
+                color: None,
+            }),
+            (Some("Kd"), Some(material)) if values.len() >= 3 => {
+                let alpha = material.color.map_or(1.0, |c| c[3]);
+                material.color = Some([values[0], values[1], values[2], alpha]);
+            }
+            (Some(keyword @ ("d" | "Tr")), Some(material)) if !values.is_empty() => {
+                let alpha = if keyword == "d" {
+                    values[0]
+                } else {
+                    1.0 - values[0]
+                };
+                let [r, g, b, _] = material.color.unwrap_or([1.0; 4]);
+                material.color = Some([r, g, b, alpha.clamp(0.0, 1.0)]);
+            }
+            _ => {}
+        }
+    }
+
+    materials
+}
@@ -0,0 +1,283 @@
+// Wavefront OBJ
+//
+// Only the geometry is read: `v` positions and `f` faces, whose corners may carry
+// texture and normal indices (`v/vt/vn`) that are ignored. Indices start at 1 and
+// negative ones count back from the last vertex read so far. Polygons are split into
+// a fan around their first corner. Every `o`, `g` or `usemtl` record starts a new
+// group, so faces can later be attributed to objects and materials.
+
+use std::ops::Range;
+
+use crate::{
+    error::AppError,
+    model::{
+        MeshParser, Triangle,
+        material::{Material, Materials},
+        mesh::IndexedMesh,
+    },
+};
+
+/// material of faces that come before any `usemtl` record
+const DEFAULT_MATERIAL: &str = "default";
+
+/// bytes looked at to recognise an OBJ file
+const PREVIEW: usize = 4096;
+
+/// records an OBJ file usually starts with
+const KEYWORDS: [&str; 10] = [
+    "#", "v ", "vn ", "vt ", "f ", "o ", "g ", "s ", "mtllib ", "usemtl ",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Group {
+    /// name from the last `o` or `g` record
+    pub name: Option<String>,
+    /// material from the last `usemtl` record
+    pub material: Option<String>,
+    /// faces of the mesh in this group
+    pub faces: Range<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Obj {
+    pub mesh: IndexedMesh,
+    pub groups: Vec<Group>,
+    /// material libraries referenced by `mtllib`
+    pub libraries: Vec<String>,
+}
+
+impl Obj {
+    /// Material of every face from the `usemtl` records, with the colours taken from
+    /// `library` where it defines them. `None` when the file uses no materials.
+    pub fn materials(&self, library: &[Material]) -> Option<Materials> {
+        if self.groups.iter().all(|group| group.material.is_none()) {
+            return None;
+        }
+
+        let mut materials = Materials::default();
+        materials.assignment.resize(self.mesh.faces.len(), None);
+        for group in self.groups.iter() {
+            let name = group.material.as_deref().unwrap_or(DEFAULT_MATERIAL);
+            let index = materials.index(name);
+            materials.assignment[group.faces.clone()].fill(Some(index));
+        }
+        for material in materials.materials.iter_mut() {
+            if let Some(defined) = library.iter().find(|m| m.name == material.name) {
+                material.color = defined.color;
+            }
+        }
+
+        Some(materials)
+    }
+}
+
+/// Whether the first statement after any comments is an OBJ one, and the file has
+/// vertices and faces anywhere. Exporters may write long headers of comments,
+/// `mtllib` and `o` records before the first vertex.
+pub fn validate_bytes(bytes: &[u8]) -> bool {
+    let mut statements = bytes
+        .split(|&b| b == b'\n')
+        .map(<[u8]>::trim_ascii_start)
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"));
+    let has = |keyword: &[u8]| {
+        bytes
+            .split(|&b| b == b'\n')
+            .any(|line| line.trim_ascii_start().starts_with(keyword))
+    };
+    statements.next().is_some_and(|line| {
+        KEYWORDS
+            .iter()
+            .any(|keyword| line.starts_with(keyword.as_bytes()))
+    }) && has(b"v ")
+        && has(b"f ")
+}
+
+/// whether the start of the file looks like an OBJ file
+pub fn detect(bytes: &[u8]) -> bool {
+    let preview = String::from_utf8_lossy(&bytes[..bytes.len().min(PREVIEW)]);
+    let mut lines = preview
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| !line.is_empty());
+    let has = |keyword: &str| {
+        preview
+            .lines()
+            .any(|line| line.trim_start().starts_with(keyword))
+    };
+    lines
+        .next()
+        .is_some_and(|line| KEYWORDS.iter().any(|keyword| line.starts_with(keyword)))
+        && has("v ")
+        && has("f ")
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Obj, AppError> {
+    let content = String::from_utf8_lossy(bytes);
+    let mut obj = Obj::default();
+    let mut current = Group::default();
+    let mut polygon: Vec<u32> = Vec::new();
+
+    // a trailing backslash joins a line with the next one
+    let content = content.replace("\\\r\n", " ").replace("\\\n", " ");
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut fields = line.split_whitespace();
+        let Some(keyword) = fields.next() else {
+            continue;
+        };
+        let error = |message: &str| {
+            AppError::bad_request(format!(
+                "invalid OBJ file, line {}: {}",
+                number + 1,
+                message
+            ))
+        };
+
+        match keyword {
+            "v" => {
+                let mut position = [0.0f32; 3];
+                for value in position.iter_mut() {
+                    *value = fields
+                        .next()
+                        .and_then(|field| field.parse().ok())
+                        .ok_or_else(|| error("vertex needs three coordinates"))?;
+                }
+                obj.mesh.add_vertex(position)?;
+            }
+            "f" => {
+                polygon.clear();
+                for corner in fields {
+                    let index = corner.split('/').next().unwrap_or("");
+                    let index: i64 = index.parse().map_err(|_| error("invalid face index"))?;
+                    let count = obj.mesh.vertices.len() as i64;
+                    let resolved = match index {
+                        i if i > 0 => i - 1,
+                        i if i < 0 => count + i,
+                        _ => return Err(error("face index 0 is not allowed")),
+                    };
+                    if !(0..count).contains(&resolved) {
+                        return Err(error("face refers to a missing vertex"));
+                    }
+                    polygon.push(resolved as u32);
+                }
+                if polygon.len() < 3 {
+                    return Err(error("face needs at least three vertices"));
+                }
+                obj.mesh.add_polygon(&polygon)?;
+            }
+            "o" | "g" | "usemtl" => {
+                let value = fields.collect::<Vec<_>>().join(" ");
+                let value = (!value.is_empty()).then_some(value);
+                let mut next = current.clone();
+                if keyword == "usemtl" {
+                    next.material = value;
+                } else {
+                    next.name = value;
+                }
+                start_group(&mut obj, &mut current, next);
+            }
+            "mtllib" => obj.libraries.extend(fields.map(str::to_string)),
+            // normals, texture coordinates, smoothing groups, curves and the like
+            _ => {}
+        }
+    }
+
+    start_group(&mut obj, &mut current, Group::default());
+
+    if obj.mesh.faces.is_empty() {
+        return Err(AppError::bad_request("OBJ file contains no faces"));
+    }
+
+    Ok(obj)
+}
+
+/// close the current group if it has faces and continue with `next`
+fn start_group(obj: &mut Obj, current: &mut Group, mut next: Group) {
+    let end = obj.mesh.faces.len();
+    current.faces.end = end;
+    if !current.faces.is_empty() {
+        obj.groups.push(current.clone());
+    }
+    next.faces = end..end;
+    *current = next;
+}
+
+pub struct ObjParser;
+
+impl MeshParser for ObjParser {
+    fn parse(bytes: &[u8]) -> Result<Vec<Triangle>, AppError> {
+        Ok(parse(bytes)?.mesh.to_triangles())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate;
+
+    const CUBE: &str = "\
+o cube
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+usemtl red
+f 1 4 3 2
+f 5 6 7 8
+usemtl blue
+f 1 2 6 5
+f 3 4 8 7
+f -8/1 -4/1 -1/1 -5/1
+f 2/1/1 3/1/1 7/1/1 6/1/1
+";
+
+    #[test]
+    fn cube_with_materials() {
+        let obj = parse(CUBE.as_bytes()).unwrap();
+        let triangles = obj.mesh.to_triangles();
+        assert_eq!(triangles.len(), 12);
+        assert!((calculate::volume(&triangles) - 1.0).abs() < 1e-5);
+
+        let materials = obj.materials(&[]).unwrap();
+        let names: Vec<&str> = materials
+            .materials
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect();
+        assert_eq!(names, ["red", "blue"]);
+        assert_eq!(materials.assignment[..4], [Some(0); 4]);
+        assert_eq!(materials.assignment[4..], [Some(1); 8]);
+    }
+
+    #[test]
+    fn detection_needs_vertices_and_faces() {
+        assert!(detect(CUBE.as_bytes()));
+        assert!(validate_bytes(CUBE.as_bytes()));
+        assert!(!detect(b"# points only\nv 0 0 0\nv 1 0 0\n"));
+        assert!(!detect(b"solid cube\nfacet normal 0 0 1\n"));
+
+        // the first vertex may come long after the header
+        let comments = "# exported by a CAD tool\n".repeat(400);
+        let header = format!("{}mtllib cube.mtl\n", comments);
+        assert!(validate_bytes(format!("{}{}", header, CUBE).as_bytes()));
+        assert!(!validate_bytes(
+            format!("{}solid cube\n{}", comments, CUBE).as_bytes()
+        ));
+        assert!(!validate_bytes(b"# points only\nv 0 0 0\nv 1 0 0\n"));
+    }
+
+    #[test]
+    fn rejects_bad_records() {
+        let error = |content: &str| parse(content.as_bytes()).unwrap_err().to_string();
+        assert!(error("v 0 0 nan\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").contains("finite"));
+        assert!(error("v 0 0 inf\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").contains("finite"));
+        assert!(error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n").contains("missing vertex"));
+        assert!(error("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n").contains("index 0"));
+        assert!(error("v 0 0 0\n").contains("no faces"));
+    }
+}
@@ -0,0 +1,349 @@
+// Object File Format
+//
+// The keyword, `OFF` with optional `ST`, `C`, `N` and `4` prefixes, is followed by
+// the vertex, face and edge counts, then the vertices and the faces as a corner
+// count followed by the vertex indices. The file is read as a stream of numbers, so
+// a vertex or face may be spread over several lines. Vertices may carry a normal, a
+// colour (`COFF`) and texture coordinates after their position, faces may end with
+// a colour; these optional values run to the end of the line. Colours are written
+// either from 0 to 1 or from 0 to 255, which is decided once for the whole file.
+//
+// Faces take their own colour or the average colour of their corners as their
+// material. Colour map indices are ignored.
+
+use crate::{
+    error::AppError,
+    model::{
+        MeshParser, Triangle,
+        material::{self, Materials},
+        mesh::IndexedMesh,
+    },
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Off {
+    pub mesh: IndexedMesh,
+    /// colour of every triangle, when the file has colours
+    pub colors: Option<Vec<[u8; 4]>>,
+}
+
+impl Off {
+    /// The colour of every face as materials, `None` without colours.
+    pub fn materials(&self) -> Option<Materials> {
+        self.colors
+            .as_ref()
+            .map(|colors| Materials::from_colors(colors))
+    }
+}
+
+/// what the prefixes of the keyword add to every vertex
+#[derive(Debug, Clone, Copy, Default)]
+struct Layout {
+    texture: bool,
+    color: bool,
+    normal: bool,
+    homogeneous: bool,
+}
+
+impl Layout {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        let mut rest = keyword.strip_suffix("OFF")?;
+        let mut layout = Layout::default();
+        for (prefix, flag) in [
+            ("ST", &mut layout.texture),
+            ("C", &mut layout.color),
+            ("N", &mut layout.normal),
+            ("4", &mut layout.homogeneous),
+        ] {
+            if let Some(stripped) = rest.strip_prefix(prefix) {
+                *flag = true;
+                rest = stripped;
+            }
+        }
+        rest.is_empty().then_some(layout)
+    }
+}
+
+pub fn validate_bytes(bytes: &[u8]) -> bool {
+    detect(bytes)
+}
+
+/// whether the first word of the file is an OFF keyword
+pub fn detect(bytes: &[u8]) -> bool {
+    let preview = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+    preview
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .find(|line| !line.is_empty())
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(Layout::from_keyword)
+        .is_some()
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Off, AppError> {
+    let content = String::from_utf8_lossy(bytes);
+    let mut tokens = Tokens::new(&content);
+
+    let layout = tokens
+        .next()
+        .and_then(Layout::from_keyword)
+        .ok_or_else(|| error("missing OFF keyword"))?;
+
+    let [vertices, faces] =
+        [(); 2].map(|_| tokens.next().and_then(|count| count.parse::<usize>().ok()));
+    let (Some(vertices), Some(faces)) = (vertices, faces) else {
+        return Err(error("invalid vertex or face count"));
+    };
+    // the edge count is of no use
+    tokens.rest_of_line();
+    if vertices > bytes.len() || faces > bytes.len() {
+        return Err(error("the counts exceed the file"));
+    }
+
+    let mut off = Off::default();
+    let mut vertex_colors: Vec<[f32; 4]> = Vec::new();
+    let dimensions = if layout.homogeneous { 4 } else { 3 };
+    let color_start = dimensions + if layout.normal { 3 } else { 0 };
+    // values every vertex must have, anything after them is on the same line
+    let required = color_start + if layout.color { 3 } else { 0 };
+    let mut values: Vec<f32> = Vec::new();
+    for _ in 0..vertices {
+        values.clear();
+        for _ in 0..required {
+            values.push(number(tokens.next())?);
+        }
+        for token in tokens.rest_of_line() {
+            values.push(number(Some(token))?);
+        }
+
+        let w = if layout.homogeneous { values[3] } else { 1.0 };
+        if w == 0.0 {
+            return Err(error("vertex at infinity"));
+        }
+        // a tiny w can push a finite position out to infinity
+        off.mesh
+            .add_vertex([values[0] / w, values[1] / w, values[2] / w])?;
+
+        if layout.color {
+            vertex_colors.push(color(&values[color_start..]));
+        }
+    }
+
+    let mut face_colors: Vec<Option<[f32; 4]>> = Vec::new();
+    let mut polygon: Vec<u32> = Vec::new();
+    for _ in 0..faces {
+        let corners: usize = tokens
+            .next()
+            .ok_or_else(|| error("the file ends early"))?
+            .parse()
+            .map_err(|_| error("invalid face"))?;
+        if corners > bytes.len() {
+            return Err(error("face has more vertices than the file"));
+        }
+        polygon.clear();
+        for _ in 0..corners {
+            let index = tokens
+                .next()
+                .ok_or_else(|| error("the file ends early"))?
+                .parse()
+                .map_err(|_| error("invalid vertex index"))?;
+            polygon.push(index);
+        }
+        if polygon.len() < 3 {
+            return Err(error("face needs at least three vertices"));
+        }
+        off.mesh.add_polygon(&polygon)?;
+
+        values.clear();
+        for token in tokens.rest_of_line() {
+            values.push(number(Some(token))?);
+        }
+        let own = (values.len() >= 3).then(|| color(&values));
+        face_colors.resize(off.mesh.faces.len(), own);
+    }
+
+    if off.mesh.faces.is_empty() {
+        return Err(error("the file contains no faces"));
+    }
+
+    if face_colors.iter().any(Option::is_some) || !vertex_colors.is_empty() {
+        // 0 to 255 when any channel anywhere is above 1
+        let scale = if vertex_colors
+            .iter()
+            .chain(face_colors.iter().flatten())
+            .flatten()
+            .any(|&c| c > 1.0)
+        {
+            255.0
+        } else {
+            1.0
+        };
+        let normalize = |rgba: &[f32; 4]| {
+            rgba.map(|c| {
+                if c.is_nan() {
+                    1.0
+                } else {
+                    (c / scale).clamp(0.0, 1.0)
+                }
+            })
+        };
+        let vertex_colors: Vec<[f32; 4]> = vertex_colors.iter().map(normalize).collect();
+
+        let white = [u8::MAX; 4];
+        off.colors = Some(
+            off.mesh
+                .faces
+                .iter()
+                .zip(face_colors.iter())
+                .map(|(face, own)| match own {
+                    Some(rgba) => normalize(rgba).map(|c| (c * 255.0).round() as u8),
+                    None if !vertex_colors.is_empty() => material::face_color(&vertex_colors, face),
+                    None => white,
+                })
+                .collect(),
+        );
+    }
+
+    Ok(off)
+}
+
+/// Whitespace separated values of the file with comments left out, keeping track of
+/// the line they are on.
+struct Tokens<'a> {
+    lines: std::str::Lines<'a>,
+    line: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            lines: content.lines(),
+            line: "".split_whitespace(),
+        }
+    }
+
+    /// the next value, on this line or a later one
+    fn next(&mut self) -> Option<&'a str> {
+        loop {
+            if let Some(token) = self.line.next() {
+                return Some(token);
+            }
+            let line = self.lines.next()?;
+            self.line = line.split('#').next().unwrap_or("").split_whitespace();
+        }
+    }
+
+    /// the values left on the current line
+    fn rest_of_line(&mut self) -> std::str::SplitWhitespace<'a> {
+        std::mem::replace(&mut self.line, "".split_whitespace())
+    }
+}
+
+fn number(token: Option<&str>) -> Result<f32, AppError> {
+    token
+        .ok_or_else(|| error("the file ends early"))?
+        .parse()
+        .map_err(|_| error("invalid number"))
+}
+
+/// RGB or RGBA as written, a missing alpha is NaN until the scale is known
+fn color(values: &[f32]) -> [f32; 4] {
+    [0, 1, 2, 3].map(|i| values.get(i).copied().unwrap_or(f32::NAN))
+}
+
+fn error(message: impl std::fmt::Display) -> AppError {
+    AppError::bad_request(format!("invalid OFF file: {}", message))
+}
+
+pub struct OffParser;
+
+impl MeshParser for OffParser {
+    fn parse(bytes: &[u8]) -> Result<Vec<Triangle>, AppError> {
+        Ok(parse(bytes)?.mesh.to_triangles())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate;
+
+    const TETRAHEDRON: &str = "0 0 0\n1 0 0\n0 1 0\n0 0 1\n";
+
+    #[test]
+    fn cube_spread_over_lines() {
+        let content = "# unit cube\nOFF\n8 6 12\n\
+0 0 0\n1 0 0\n1 1\n0\n0 1 0\n0 0 1\n1 0 1\n1 1 1 # top corner\n0 1 1\n\
+4 0 3 2 1\n4 4 5 6 7\n4\n0 1 5 4\n4 2 3 7 6\n4 0 4 7 3\n4 1 2 6 5\n";
+        assert!(detect(content.as_bytes()));
+        let off = parse(content.as_bytes()).unwrap();
+        assert_eq!(off.mesh.faces.len(), 12);
+        assert!(off.colors.is_none());
+        assert!((calculate::volume(&off.mesh.to_triangles()) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn homogeneous_vertices() {
+        let content = "4OFF\n4 4 0\n0 0 0 2\n2 0 0 2\n0 2 0 2\n0 0 2 2\n\
+3 0 2 1\n3 0 1 3\n3 0 3 2\n3 1 2 3\n";
+        let off = parse(content.as_bytes()).unwrap();
+        assert_eq!(off.mesh.vertices[1], [1.0, 0.0, 0.0]);
+        assert!((calculate::volume(&off.mesh.to_triangles()) - 1.0 / 6.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn vertex_colours_from_zero_to_one() {
+        let content = "COFF\n4 4 0\n0 0 0 1 0 0\n1 0 0 1 0 0\n0 1 0 1 0 0\n0 0 1 0 0 1 0.8\n\
+3 0 2 1\n3 0 1 3\n3 0 3 2\n3 1 2 3\n";
+        let off = parse(content.as_bytes()).unwrap();
+        let colors = off.colors.as_ref().unwrap();
+        assert_eq!(colors[0], [255, 0, 0, 255]);
+        assert_eq!(colors[1], [170, 0, 85, 238]);
+        assert_eq!(off.materials().unwrap().materials[0].name, "#FF0000");
+    }
+
+    #[test]
+    fn face_colours_share_one_scale() {
+        let content = format!(
+            "OFF\n4 4 0\n{}3 0 2 1 1 0 0\n3 0 1 3 0 0 255\n3 0 3 2\n3 1 2 3 0 255 0 128\n",
+            TETRAHEDRON
+        );
+        let off = parse(content.as_bytes()).unwrap();
+        assert_eq!(
+            off.colors.unwrap(),
+            vec![[1, 0, 0, 255], [0, 0, 255, 255], [255; 4], [0, 255, 0, 128]]
+        );
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        for (content, reason) in [
+            (
+                format!("OFF\n4 1 0\n{}3 0 1 4\n", TETRAHEDRON),
+                "missing vertex",
+            ),
+            (
+                format!("OFF\n4 2 0\n{}3 0 1 2\n", TETRAHEDRON),
+                "ends early",
+            ),
+            (
+                format!("OFF\n4 1 0\n{}2 0 1\n", TETRAHEDRON),
+                "three vertices",
+            ),
+            ("OFF\n4 1\n0 0 0\n".to_string(), "ends early"),
+            ("OFF\nfour 1 0\n".to_string(), "count"),
+            (
+                "OFF\n3 1 0\n0 0 nan\n1 0 0\n0 1 0\n3 0 1 2\n".to_string(),
+                "finite",
+            ),
+            (
+                "4OFF\n3 1 0\n1e30 0 0 1e-30\n1 0 0 1\n0 1 0 1\n3 0 1 2\n".to_string(),
+                "finite",
+            ),
+        ] {
+            let error = parse(content.as_bytes()).unwrap_err().to_string();
+            assert!(error.contains(reason), "{}: {}", reason, error);
+        }
+        assert!(!detect(b"NOFFS\n4 4 0\n"));
+    }
+}
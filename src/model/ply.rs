@@ -0,0 +1,451 @@
+// Polygon File Format
+//
+// A text header declares the elements of the file with their properties, the body
+// holds them in that order as ASCII, little endian or big endian binary. Any element
+// and property is accepted; only the vertex positions and colours and the vertex
+// lists of the faces are kept. Polygons are split into a fan around their first
+// corner.
+//
+// Faces take the average colour of their corners as their material.
+
+use crate::{
+    error::AppError,
+    model::{
+        MeshParser, Triangle,
+        material::{self, Materials},
+        mesh::IndexedMesh,
+    },
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Ply {
+    pub mesh: IndexedMesh,
+    /// RGBA from 0 to 1 for every vertex, when the file has colours
+    pub colors: Option<Vec<[f32; 4]>>,
+}
+
+impl Ply {
+    /// The colour of every face as materials, `None` without vertex colours.
+    pub fn materials(&self) -> Option<Materials> {
+        let colors = self.colors.as_ref()?;
+        let faces: Vec<[u8; 4]> = self
+            .mesh
+            .faces
+            .iter()
+            .map(|face| material::face_color(colors, face))
+            .collect();
+        Some(Materials::from_colors(&faces))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// largest value of integer types, colours are scaled by it
+    fn range(self) -> f64 {
+        match self {
+            Self::U8 => u8::MAX as f64,
+            Self::U16 => u16::MAX as f64,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Self::Scalar(name, _) | Self::List(name, _, _) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// reads the values of the body one after the other
+struct Body<'a> {
+    encoding: Encoding,
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, AppError> {
+        if self.encoding == Encoding::Ascii {
+            let rest = &self.bytes[self.position..];
+            let start = rest
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .ok_or_else(|| error("the file ends early"))?;
+            let length = rest[start..]
+                .iter()
+                .position(u8::is_ascii_whitespace)
+                .unwrap_or(rest.len() - start);
+            self.position += start + length;
+            return std::str::from_utf8(&rest[start..start + length])
+                .ok()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| error("invalid number"));
+        }
+
+        let size = scalar.size();
+        let raw = self
+            .bytes
+            .get(self.position..self.position + size)
+            .ok_or_else(|| error("the file ends early"))?;
+        self.position += size;
+
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(raw);
+        if self.encoding == Encoding::BigEndian {
+            buffer[..size].reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => buffer[0] as i8 as f64,
+            Scalar::U8 => buffer[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+}
+
+pub fn validate_bytes(bytes: &[u8]) -> bool {
+    detect(bytes) && header_end(bytes).is_some()
+}
+
+pub fn detect(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"ply\n") || bytes.starts_with(b"ply\r\n")
+}
+
+/// offset of the body, just after the `end_header` line
+fn header_end(bytes: &[u8]) -> Option<usize> {
+    let mut end = 0;
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        end += line.len();
+        if line.ends_with(b"\n") && line.trim_ascii() == b"end_header" {
+            return Some(end);
+        }
+    }
+    None
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Ply, AppError> {
+    if !detect(bytes) {
+        return Err(error("missing ply signature"));
+    }
+    let body_start = header_end(bytes).ok_or_else(|| error("missing end_header"))?;
+    let header = String::from_utf8_lossy(&bytes[..body_start]);
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in header.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    other => return Err(error(format!("unknown format {}", other))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("invalid count of {}", name)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let (Some(count), Some(item)) = (Scalar::from_name(count), Scalar::from_name(item))
+                else {
+                    return Err(error(format!("unknown type of property {}", name)));
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property outside of an element"))?
+                    .properties
+                    .push(Property::List(name.to_string(), count, item));
+            }
+            ["property", kind, name] => {
+                let scalar = Scalar::from_name(kind)
+                    .ok_or_else(|| error(format!("unknown type of property {}", name)))?;
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property outside of an element"))?
+                    .properties
+                    .push(Property::Scalar(name.to_string(), scalar));
+            }
+            ["comment", ..] | ["obj_info", ..] | ["end_header"] | [] => {}
+            _ => return Err(error(format!("invalid header line {}", line))),
+        }
+    }
+    let encoding = encoding.ok_or_else(|| error("missing format"))?;
+
+    let mut body = Body {
+        encoding,
+        bytes: &bytes[body_start..],
+        position: 0,
+    };
+    let mut ply = Ply::default();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut polygon: Vec<u32> = Vec::new();
+
+    for element in elements.iter() {
+        // the smallest record is one byte, larger counts can not be in the file
+        if element.count > bytes.len() {
+            return Err(error(format!("too many {} elements", element.name)));
+        }
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        let position = ["x", "y", "z"].map(|axis| property(element, axis));
+        let color = [
+            ["red", "diffuse_red", "r"],
+            ["green", "diffuse_green", "g"],
+            ["blue", "diffuse_blue", "b"],
+            ["alpha", "diffuse_alpha", "a"],
+        ]
+        .map(|names| names.iter().find_map(|name| property(element, name)));
+        let has_color = is_vertex && color[..3].iter().all(Option::is_some);
+        if is_vertex && position.iter().any(Option::is_none) {
+            return Err(error("vertices need x, y and z"));
+        }
+
+        for _ in 0..element.count {
+            let mut vertex = [0.0f32; 3];
+            let mut rgba = [1.0f32; 4];
+            polygon.clear();
+
+            for (index, property) in element.properties.iter().enumerate() {
+                match property {
+                    Property::Scalar(_, scalar) => {
+                        let value = body.read(*scalar)?;
+                        if let Some(axis) = position.iter().position(|&p| p == Some(index)) {
+                            vertex[axis] = value as f32;
+                        }
+                        if let Some(channel) = color.iter().position(|&c| c == Some(index)) {
+                            rgba[channel] = (value / scalar.range()) as f32;
+                        }
+                    }
+                    Property::List(name, count, item) => {
+                        let count = body.read(*count)?;
+                        if !(0.0..=u16::MAX as f64).contains(&count) {
+                            return Err(error(format!("invalid length of {}", name)));
+                        }
+                        let keep =
+                            is_face && matches!(name.as_str(), "vertex_indices" | "vertex_index");
+                        for _ in 0..count as usize {
+                            let value = body.read(*item)?;
+                            if keep {
+                                if !(0.0..=u32::MAX as f64).contains(&value) || value.fract() != 0.0
+                                {
+                                    return Err(error("invalid face index"));
+                                }
+                                polygon.push(value as u32);
+                            }
+                        }
+                        if keep && polygon.len() < 3 {
+                            return Err(error("face needs at least three vertices"));
+                        }
+                    }
+                }
+            }
+
+            if is_vertex {
+                ply.mesh.add_vertex(vertex)?;
+                if has_color {
+                    colors.push(rgba.map(|c| c.clamp(0.0, 1.0)));
+                }
+            }
+            if is_face && !polygon.is_empty() {
+                if polygon
+                    .iter()
+                    .any(|&v| v as usize >= ply.mesh.vertices.len())
+                {
+                    return Err(error("face refers to a missing vertex"));
+                }
+                ply.mesh.add_polygon(&polygon)?;
+            }
+        }
+    }
+
+    if ply.mesh.faces.is_empty() {
+        return Err(error("the file contains no faces"));
+    }
+    if !colors.is_empty() {
+        ply.colors = Some(colors);
+    }
+
+    Ok(ply)
+}
+
+/// index of the scalar property called `name`
+fn property(element: &Element, name: &str) -> Option<usize> {
+    element
+        .properties
+        .iter()
+        .position(|property| matches!(property, Property::Scalar(..)) && property.name() == name)
+}
+
+fn error(message: impl std::fmt::Display) -> AppError {
+    AppError::bad_request(format!("invalid PLY file: {}", message))
+}
+
+pub struct PlyParser;
+
+impl MeshParser for PlyParser {
+    fn parse(bytes: &[u8]) -> Result<Vec<Triangle>, AppError> {
+        Ok(parse(bytes)?.mesh.to_triangles())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate;
+
+    const VERTICES: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, 1.0],
+    ];
+    const FACES: [[i32; 3]; 4] = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+
+    /// tetrahedron with the body in little endian binary
+    fn binary() -> Vec<u8> {
+        let mut bytes = b"ply\nformat binary_little_endian 1.0\ncomment end_header is not here\n\
+element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+element face 4\nproperty list uchar int vertex_indices\nend_header\n"
+            .to_vec();
+        for vertex in VERTICES {
+            vertex.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        }
+        for face in FACES {
+            bytes.push(3);
+            face.iter().for_each(|i| bytes.extend(i.to_le_bytes()));
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_with_colours() {
+        let content = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+property float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+element face 4\nproperty list uchar int vertex_indices\nend_header\n\
+0 0 0 255 0 0\n1 0 0 255 0 0\n0 1 0 255 0 0\n0 0 1 0 0 255\n\
+3 0 2 1\n3 0 1 3\n3 0 3 2\n3 1 2 3\n";
+        let ply = parse(content.as_bytes()).unwrap();
+        assert!((calculate::volume(&ply.mesh.to_triangles()) - 1.0 / 6.0).abs() < 1e-5);
+        let colors = ply.colors.as_ref().unwrap();
+        assert_eq!(colors[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(colors[3], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(ply.materials().unwrap().materials[0].name, "#FF0000");
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        let ply = parse(&binary()).unwrap();
+        assert_eq!(ply.mesh.faces.len(), 4);
+        assert!((calculate::volume(&ply.mesh.to_triangles()) - 1.0 / 6.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let bytes = binary();
+        let error = parse(&bytes[..bytes.len() - 3]).unwrap_err().to_string();
+        assert!(error.contains("ends early"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_bad_face_indices() {
+        let face = |index: &str| {
+            format!(
+                "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+property float z\nelement face 1\nproperty list uchar float vertex_indices\nend_header\n\
+0 0 0\n1 0 0\n0 1 0\n3 0 1 {}\n",
+                index
+            )
+        };
+        assert!(parse(face("2").as_bytes()).is_ok());
+        for index in ["-1", "1.5", "nan"] {
+            let error = parse(face(index).as_bytes()).unwrap_err().to_string();
+            assert!(error.contains("invalid face index"), "{}: {}", index, error);
+        }
+    }
+
+    #[test]
+    fn rejects_non_finite_vertices() {
+        let ascii = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n\
+0 0 0\n1 nan 0\n0 1 0\n3 0 1 2\n";
+        let error = parse(ascii.as_bytes()).unwrap_err().to_string();
+        assert!(error.contains("finite"), "{}", error);
+
+        let mut bytes = binary();
+        let header = bytes.len() - 4 * 12 - 4 * 13;
+        bytes[header + 4..header + 8].copy_from_slice(&f32::INFINITY.to_le_bytes());
+        let error = parse(&bytes).unwrap_err().to_string();
+        assert!(error.contains("finite"), "{}", error);
+    }
+
+    #[test]
+    fn end_header_only_as_a_line() {
+        assert!(header_end(b"ply\ncomment no end_header here\n").is_none());
+        assert_eq!(header_end(b"ply\nend_header\r\nbody"), Some(16));
+    }
+}
@@ -9,7 +9,7 @@ use std::io::Cursor;
 
 use crate::{
     error::AppError,
-    model::{MAX_TRIANGLES, MeshParser},
+    model::{MAX_TRIANGLES, MeshParser, mesh},
 };
 
 pub fn validate_bytes(bytes: &[u8]) -> bool {
@@ -63,7 +63,36 @@ impl MeshParser for STlParser {
                 }
             })
             .collect();
+        mesh::check_triangles(&triangles)?;
 
         Ok(triangles)
     }
 }
+
+/// serialise triangles as a binary STL file
+pub fn to_binary(triangles: &[super::Triangle]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(84 + triangles.len() * 50);
+    let mut header = [0u8; 80];
+    let title = b"slicer_rs";
+    header[..title.len()].copy_from_slice(title);
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+
+    for triangle in triangles {
+        let [a, b, c] = triangle.vertices.map(nalgebra::Vector3::from);
+        let normal = (b - a).cross(&(c - a));
+        let normal = normal.try_normalize(f32::EPSILON).unwrap_or(normal);
+
+        for value in normal
+            .iter()
+            .chain(a.iter())
+            .chain(b.iter())
+            .chain(c.iter())
+        {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 0]); // attribute byte count
+    }
+
+    bytes
+}
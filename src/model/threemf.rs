@@ -0,0 +1,599 @@
+// 3D Manufacturing Format
+//
+// A 3MF file is a zip package. Its root model part, named in `_rels/.rels`, holds
+// mesh objects and objects assembled from components of other objects, each with an
+// affine transform. Only what the build items place is printed, so the triangles are
+// collected by walking the build down through the components. Components and items
+// of the production extension may refer to objects in other model parts of the
+// package. Coordinates are converted from the unit of their part to millimetres.
+//
+// Triangles take their material from the base materials or colour groups their
+// `pid` and `p1` refer to, falling back to the `pid` and `pindex` of their object.
+// Every base material and every distinct colour becomes a material of the model,
+// full colour models with many distinct colours get one full colour material.
+// Textures, composite materials and multi-properties are not resolved, triangles
+// using them are left without a material.
+
+use std::{collections::HashMap, io::Cursor, ops::Range};
+
+use zip::ZipArchive;
+
+use crate::{
+    error::AppError,
+    model::{
+        self, MAX_DEPTH, MAX_TRIANGLES, MeshParser, Triangle,
+        archive::{self, Budget},
+        material::{Material, Materials},
+        mesh::{self, IndexedMesh},
+        transform::Transform,
+    },
+};
+
+/// where the root model part usually lives when the relationships do not say
+const ROOT_MODEL: &str = "3D/3dmodel.model";
+const RELATIONSHIPS: &str = "_rels/.rels";
+const MODEL_RELATIONSHIP: &str = "http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel";
+
+/// what one build item puts on the plate
+#[derive(Debug, Clone)]
+pub struct Item {
+    /// name of the placed object
+    pub name: Option<String>,
+    /// id of the placed object in its model part
+    pub object: u32,
+    /// triangles of the model belonging to this item
+    pub faces: Range<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ThreeMf {
+    /// every build item in place, in millimetres
+    pub triangles: Vec<Triangle>,
+    pub items: Vec<Item>,
+    /// `None` when no triangle refers to a material or colour
+    pub materials: Option<Materials>,
+}
+
+#[derive(Debug, Clone)]
+struct Component {
+    object: u32,
+    /// model part holding the object, `None` for the part of the parent
+    path: Option<String>,
+    transform: Transform,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Object {
+    name: Option<String>,
+    /// support and other objects are not part of the printed model
+    printable: bool,
+    mesh: IndexedMesh,
+    /// property group and index of every face
+    properties: Vec<Option<(u32, usize)>>,
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Default)]
+struct Part {
+    objects: HashMap<u32, Object>,
+    /// base materials and colour groups by resource id
+    groups: HashMap<u32, Vec<Material>>,
+    build: Vec<Component>,
+}
+
+struct Package<'a> {
+    archive: ZipArchive<Cursor<&'a [u8]>>,
+    /// every part read from the package is paid for out of this
+    budget: &'a mut Budget,
+    parts: HashMap<String, Part>,
+    materials: Materials,
+    placements: usize,
+}
+
+pub fn validate_bytes(bytes: &[u8]) -> bool {
+    detect(bytes)
+}
+
+/// whether the bytes are a zip package with a 3D model part
+pub fn detect(bytes: &[u8]) -> bool {
+    archive::is_zip(bytes)
+        && ZipArchive::new(Cursor::new(bytes)).is_ok_and(|archive| {
+            archive
+                .file_names()
+                .any(|name| name.to_lowercase().ends_with(".model"))
+        })
+}
+
+pub fn parse(bytes: &[u8]) -> Result<ThreeMf, AppError> {
+    parse_with_budget(bytes, &mut Budget::default())
+}
+
+/// Parse a package whose parts draw on `budget`, like one nested in an archive.
+pub fn parse_with_budget(bytes: &[u8], budget: &mut Budget) -> Result<ThreeMf, AppError> {
+    let archive = archive::open(bytes).map_err(|e| e.with_context("invalid 3MF file:"))?;
+    let mut package = Package {
+        archive,
+        budget,
+        parts: HashMap::new(),
+        materials: Materials::default(),
+        placements: 0,
+    };
+
+    let root = package.root()?;
+    package.load(&root)?;
+
+    let mut result = ThreeMf::default();
+    for item in package.parts[&root].build.clone() {
+        let path = item.path.clone().unwrap_or_else(|| root.clone());
+        let start = result.triangles.len();
+        package.place(&path, item.object, item.transform, 0, &mut result.triangles)?;
+        result.items.push(Item {
+            name: package.parts[&path]
+                .objects
+                .get(&item.object)
+                .and_then(|object| object.name.clone()),
+            object: item.object,
+            faces: start..result.triangles.len(),
+        });
+    }
+
+    if result.triangles.is_empty() {
+        return Err(error("the build contains no triangles"));
+    }
+    if package.materials.assignment.iter().any(Option::is_some) {
+        package.materials.limit_colors();
+        result.materials = Some(package.materials);
+    }
+
+    Ok(result)
+}
+
+impl Package<'_> {
+    /// name of the root model part
+    fn root(&mut self) -> Result<String, AppError> {
+        if self.archive.index_for_name(RELATIONSHIPS).is_none() {
+            return Ok(ROOT_MODEL.to_string());
+        }
+        let bytes = archive::read_entry(&mut self.archive, RELATIONSHIPS, self.budget)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let document = roxmltree::Document::parse(text.trim_start_matches('\u{feff}'))
+            .map_err(|e| AppError::bad_request_with_source("invalid 3MF relationships", e))?;
+
+        Ok(document
+            .descendants()
+            .filter(|node| node.has_tag_name("Relationship"))
+            .find(|node| node.attribute("Type") == Some(MODEL_RELATIONSHIP))
+            .and_then(|node| node.attribute("Target"))
+            .map(part_name)
+            .unwrap_or_else(|| ROOT_MODEL.to_string()))
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), AppError> {
+        if !self.parts.contains_key(path) {
+            let bytes = archive::read_entry(&mut self.archive, path, self.budget)?;
+            let part = parse_part(&bytes)?;
+            self.parts.insert(path.to_string(), part);
+        }
+        Ok(())
+    }
+
+    /// append the triangles of an object and its components, moved by `transform`
+    fn place(
+        &mut self,
+        path: &str,
+        id: u32,
+        transform: Transform,
+        depth: usize,
+        triangles: &mut Vec<Triangle>,
+    ) -> Result<(), AppError> {
+        self.placements += 1;
+        if depth > MAX_DEPTH {
+            return Err(error("components are nested too deeply"));
+        }
+        model::check_placements(self.placements)?;
+
+        self.load(path)?;
+        let object = self.parts[path]
+            .objects
+            .get(&id)
+            .ok_or_else(|| error(format!("object {} does not exist", id)))?;
+        if !object.printable {
+            return Ok(());
+        }
+
+        let start = triangles.len();
+        if start + object.mesh.faces.len() > MAX_TRIANGLES as usize {
+            return Err(AppError::bad_request(format!(
+                "too many triangles, the limit is {}",
+                MAX_TRIANGLES
+            )));
+        }
+        triangles.extend(object.mesh.to_triangles());
+        transform.apply(&mut triangles[start..]);
+        // finite matrices still overflow once enough of them are composed
+        mesh::check_triangles(&triangles[start..])?;
+
+        let groups = &self.parts[path].groups;
+        for property in object.properties.iter() {
+            let material = property.and_then(|(group, index)| groups.get(&group)?.get(index));
+            let assigned = material.map(|material| {
+                let assigned = self.materials.index(&material.name);
+                self.materials.materials[assigned as usize].color = material.color;
+                assigned
+            });
+            self.materials.assignment.push(assigned);
+        }
+
+        for component in object.components.clone() {
+            let path = component.path.as_deref().unwrap_or(path);
+            let transform = component.transform.compose(transform);
+            self.place(path, component.object, transform, depth + 1, triangles)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_part(bytes: &[u8]) -> Result<Part, AppError> {
+    let text = std::str::from_utf8(bytes).map_err(|e| {
+        AppError::bad_request_with_source("invalid 3MF file: model is not UTF-8", e)
+    })?;
+    let document = roxmltree::Document::parse(text.trim_start_matches('\u{feff}'))
+        .map_err(|e| AppError::bad_request_with_source("invalid 3MF file: malformed model", e))?;
+
+    let model = document.root_element();
+    if !model.has_tag_name("model") {
+        return Err(error("the model part has no model element"));
+    }
+    let unit = match model.attribute("unit").unwrap_or("millimeter") {
+        "micron" => 0.001,
+        "millimeter" => 1.0,
+        "centimeter" => 10.0,
+        "inch" => 25.4,
+        "foot" => 304.8,
+        "meter" => 1000.0,
+        other => return Err(error(format!("unknown unit {}", other))),
+    };
+
+    let mut part = Part::default();
+    for node in model.children().filter(|node| node.is_element()) {
+        match node.tag_name().name() {
+            "resources" => {
+                for resource in node.children().filter(|node| node.is_element()) {
+                    match resource.tag_name().name() {
+                        "object" => {
+                            let id = number(resource, "id")?;
+                            part.objects.insert(id, parse_object(resource, unit)?);
+                        }
+                        "basematerials" => {
+                            let id = number(resource, "id")?;
+                            let materials = resource
+                                .children()
+                                .filter(|n| n.has_tag_name("base"))
+                                .map(|base| Material {
+                                    name: base.attribute("name").unwrap_or_default().to_string(),
+                                    color: base.attribute("displaycolor").and_then(parse_color),
+                                })
+                                .collect();
+                            part.groups.insert(id, materials);
+                        }
+                        "colorgroup" => {
+                            let id = number(resource, "id")?;
+                            let colors = resource
+                                .children()
+                                .filter(|n| n.has_tag_name("color"))
+                                .map(|color| {
+                                    let value = color.attribute("color").unwrap_or_default();
+                                    let color = parse_color(value);
+                                    // named like the colours of the other formats
+                                    let name = color.map_or_else(
+                                        || value.to_uppercase(),
+                                        |rgba| {
+                                            Material::color_name(
+                                                rgba.map(|c| (c * 255.0).round() as u8),
+                                            )
+                                        },
+                                    );
+                                    Material { name, color }
+                                })
+                                .collect();
+                            part.groups.insert(id, colors);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "build" => {
+                for item in node.children().filter(|n| n.has_tag_name("item")) {
+                    part.build.push(parse_component(item, unit)?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(part)
+}
+
+fn parse_object(node: roxmltree::Node, unit: f32) -> Result<Object, AppError> {
+    let mut object = Object {
+        name: node.attribute("name").map(str::to_string),
+        printable: !matches!(node.attribute("type"), Some("support" | "other")),
+        ..Default::default()
+    };
+    let group: Option<u32> = optional(node, "pid")?;
+    let index: Option<usize> = optional(node, "pindex")?;
+
+    for child in node.children().filter(|node| node.is_element()) {
+        match child.tag_name().name() {
+            "mesh" => {
+                for list in child.children().filter(|node| node.is_element()) {
+                    match list.tag_name().name() {
+                        "vertices" => {
+                            for vertex in list.children().filter(|n| n.has_tag_name("vertex")) {
+                                let position: [f32; 3] = [
+                                    number(vertex, "x")?,
+                                    number(vertex, "y")?,
+                                    number(vertex, "z")?,
+                                ];
+                                object.mesh.add_vertex(position.map(|c| c * unit))?;
+                            }
+                        }
+                        "triangles" => {
+                            let count = object.mesh.vertices.len() as u32;
+                            for triangle in list.children().filter(|n| n.has_tag_name("triangle")) {
+                                let face: [u32; 3] = [
+                                    number(triangle, "v1")?,
+                                    number(triangle, "v2")?,
+                                    number(triangle, "v3")?,
+                                ];
+                                if face.iter().any(|&v| v >= count) {
+                                    return Err(error("triangle refers to a missing vertex"));
+                                }
+                                object.mesh.faces.push(face);
+
+                                let group = optional(triangle, "pid")?.or(group);
+                                let index = optional(triangle, "p1")?.or(index).unwrap_or(0);
+                                object.properties.push(group.map(|group| (group, index)));
+                            }
+                            if object.mesh.faces.len() > MAX_TRIANGLES as usize {
+                                return Err(AppError::bad_request(format!(
+                                    "too many triangles, the limit is {}",
+                                    MAX_TRIANGLES
+                                )));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "components" => {
+                for component in child.children().filter(|n| n.has_tag_name("component")) {
+                    object.components.push(parse_component(component, unit)?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(object)
+}
+
+/// a `component` or build `item`, both reference an object with a transform
+fn parse_component(node: roxmltree::Node, unit: f32) -> Result<Component, AppError> {
+    Ok(Component {
+        object: number(node, "objectid")?,
+        path: node
+            .attributes()
+            .find(|attribute| attribute.name() == "path")
+            .map(|attribute| part_name(attribute.value())),
+        transform: match node.attribute("transform") {
+            Some(value) => parse_transform(value, unit)?,
+            None => Transform::identity(),
+        },
+    })
+}
+
+/// Transforms are written as 12 values `m00 m01 m02 m10 .. m32` of a matrix that
+/// multiplies row vectors, the last row being the translation.
+fn parse_transform(value: &str, unit: f32) -> Result<Transform, AppError> {
+    let values: Vec<f32> = value
+        .split_whitespace()
+        .map(|v| v.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| error(format!("invalid transform {}", value)))?;
+    if values.len() != 12 || values.iter().any(|v| !v.is_finite()) {
+        return Err(error(format!("invalid transform {}", value)));
+    }
+
+    Ok(Transform::from_rows(std::array::from_fn(|row| {
+        [
+            values[row],
+            values[3 + row],
+            values[6 + row],
+            values[9 + row] * unit,
+        ]
+    })))
+}
+
+fn number<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, AppError> {
+    node.attribute(name)
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| {
+            error(format!(
+                "{} needs a valid {} attribute",
+                node.tag_name().name(),
+                name
+            ))
+        })
+}
+
+fn optional<T: std::str::FromStr>(
+    node: roxmltree::Node,
+    name: &str,
+) -> Result<Option<T>, AppError> {
+    node.attribute(name).map(|_| number(node, name)).transpose()
+}
+
+/// `#RRGGBB` or `#RRGGBBAA` as RGBA from 0 to 1
+fn parse_color(value: &str) -> Option<[f32; 4]> {
+    let hex = value.strip_prefix('#')?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| -> Option<f32> {
+        let bits = hex.get(i * 2..i * 2 + 2).unwrap_or("FF");
+        Some(u8::from_str_radix(bits, 16).ok()? as f32 / 255.0)
+    };
+    Some([channel(0)?, channel(1)?, channel(2)?, channel(3)?])
+}
+
+/// zip entry name of a part name like `/3D/3dmodel.model`
+fn part_name(target: &str) -> String {
+    target.trim_start_matches('/').to_string()
+}
+
+fn error(message: impl std::fmt::Display) -> AppError {
+    AppError::bad_request(format!("invalid 3MF file: {}", message))
+}
+
+pub struct ThreeMfParser;
+
+impl MeshParser for ThreeMfParser {
+    fn parse(bytes: &[u8]) -> Result<Vec<Triangle>, AppError> {
+        Ok(parse(bytes)?.triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+    use crate::calculate;
+
+    const TETRAHEDRON: &str = r#"<mesh><vertices><vertex x="0" y="0" z="0"/><vertex x="10" y="0" z="0"/><vertex x="0" y="10" z="0"/><vertex x="0" y="0" z="10"/></vertices><triangles><triangle v1="0" v2="2" v3="1"/><triangle v1="0" v2="1" v3="3"/><triangle v1="0" v2="3" v3="2"/><triangle v1="1" v2="2" v3="3"/></triangles></mesh>"#;
+
+    fn package(resources: &str, build: &str) -> Vec<u8> {
+        let model = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02"><resources>{}</resources><build>{}</build></model>"#,
+            resources, build
+        );
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file(ROOT_MODEL, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(model.as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn build_items_with_components() {
+        let bytes = package(
+            &format!(
+                r#"<object id="1" name="tetrahedron">{}</object><object id="2"><components><component objectid="1"/><component objectid="1" transform="2 0 0 0 1 0 0 0 1 20 0 0"/></components></object>"#,
+                TETRAHEDRON
+            ),
+            r#"<item objectid="1"/><item objectid="2"/>"#,
+        );
+        assert!(detect(&bytes));
+
+        let model = parse(&bytes).unwrap();
+        let volumes: Vec<f32> = model
+            .items
+            .iter()
+            .map(|item| calculate::volume(&model.triangles[item.faces.clone()]))
+            .collect();
+        let tetrahedron = 1000.0 / 6.0;
+        assert!((volumes[0] - tetrahedron).abs() < 1e-2);
+        assert!((volumes[1] - 3.0 * tetrahedron).abs() < 1e-2);
+    }
+
+    #[test]
+    fn component_cycles() {
+        let own = package(
+            r#"<object id="1"><components><component objectid="1"/></components></object>"#,
+            r#"<item objectid="1"/>"#,
+        );
+        let mutual = package(
+            r#"<object id="1"><components><component objectid="2"/></components></object><object id="2"><components><component objectid="1"/></components></object>"#,
+            r#"<item objectid="1"/>"#,
+        );
+        for bytes in [own, mutual] {
+            let error = parse(&bytes).unwrap_err().to_string();
+            assert!(error.contains("nested too deeply"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn placement_limit() {
+        // every level places the next one four times, 4^10 copies in the end
+        let mut resources = format!(r#"<object id="10">{}</object>"#, TETRAHEDRON);
+        for id in 0..10 {
+            resources.push_str(&format!(
+                r#"<object id="{}"><components>{}</components></object>"#,
+                id,
+                format!(r#"<component objectid="{}"/>"#, id + 1).repeat(4)
+            ));
+        }
+        let error = parse(&package(&resources, r#"<item objectid="0"/>"#))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("too many placed parts"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_non_finite_coordinates() {
+        let object = |mesh: &str| format!(r#"<object id="1">{}</object>"#, mesh);
+        for bytes in [
+            package(
+                &object(&TETRAHEDRON.replacen(r#"x="10""#, r#"x="NaN""#, 1)),
+                r#"<item objectid="1"/>"#,
+            ),
+            package(
+                &object(&TETRAHEDRON.replacen(r#"z="10""#, r#"z="inf""#, 1)),
+                r#"<item objectid="1"/>"#,
+            ),
+            package(
+                &object(TETRAHEDRON),
+                r#"<item objectid="1" transform="1e38 0 0 0 1 0 0 0 1 0 0 0"/>"#,
+            ),
+        ] {
+            let error = parse(&bytes).unwrap_err().to_string();
+            assert!(error.contains("finite"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn colour_names_match_the_other_formats() {
+        let mesh = TETRAHEDRON.replacen(r#"v3="1"/>"#, r#"v3="1" p1="1"/>"#, 1);
+        let bytes = package(
+            &format!(
+                r##"<colorgroup id="5"><color color="#ff0000ff"/><color color="#00ff0080"/></colorgroup><object id="1" pid="5" pindex="0">{}</object>"##,
+                mesh
+            ),
+            r#"<item objectid="1"/>"#,
+        );
+        let materials = parse(&bytes).unwrap().materials.unwrap();
+        let mut names: Vec<&str> = materials
+            .materials
+            .iter()
+            .map(|material| material.name.as_str())
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, ["#00FF0080", "#FF0000"]);
+    }
+
+    #[test]
+    fn compressed_too_much() {
+        let padding = " ".repeat(4 * 1024 * 1024);
+        let bytes = package(
+            &format!(r#"<object id="1">{}</object>{}"#, TETRAHEDRON, padding),
+            r#"<item objectid="1"/>"#,
+        );
+        let error = parse(&bytes).unwrap_err().to_string();
+        assert!(error.contains("compressed too much"), "{}", error);
+    }
+}
@@ -0,0 +1,92 @@
+use nalgebra::{Matrix3, Rotation3, Vector3};
+use rayon::prelude::*;
+
+use crate::model::Triangle;
+
+/// Affine transformation of a model, `linear * v + translation`.
+///
+/// The builder methods compose in the order they are called, each one is applied on
+/// top of the previous ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    linear: Matrix3<f32>,
+    translation: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            linear: Matrix3::identity(),
+            translation: Vector3::zeros(),
+        }
+    }
+
+    /// from a row-major 3x4 matrix, the last column being the translation
+    pub fn from_rows(rows: [[f32; 4]; 3]) -> Self {
+        Self {
+            linear: Matrix3::from_fn(|r, c| rows[r][c]),
+            translation: Vector3::from_fn(|r, _| rows[r][3]),
+        }
+    }
+
+    pub fn scale(self, factors: [f32; 3]) -> Self {
+        self.then(
+            Matrix3::from_diagonal(&Vector3::from(factors)),
+            Vector3::zeros(),
+        )
+    }
+
+    /// mirror across the planes through the origin normal to the selected axes
+    pub fn mirror(self, axes: [bool; 3]) -> Self {
+        self.scale(axes.map(|mirror| if mirror { -1.0 } else { 1.0 }))
+    }
+
+    /// rotate about the X, then the Y, then the Z axis, angles in degrees
+    pub fn rotate(self, degrees: [f32; 3]) -> Self {
+        let [x, y, z] = degrees.map(f32::to_radians);
+        let rotation = Rotation3::from_axis_angle(&Vector3::z_axis(), z)
+            * Rotation3::from_axis_angle(&Vector3::y_axis(), y)
+            * Rotation3::from_axis_angle(&Vector3::x_axis(), x);
+        self.then(rotation.into_inner(), Vector3::zeros())
+    }
+
+    pub fn translate(self, offset: [f32; 3]) -> Self {
+        self.then(Matrix3::identity(), Vector3::from(offset))
+    }
+
+    /// `other` applied after `self`
+    pub fn compose(self, other: Transform) -> Self {
+        self.then(other.linear, other.translation)
+    }
+
+    fn then(self, linear: Matrix3<f32>, translation: Vector3<f32>) -> Self {
+        Self {
+            linear: linear * self.linear,
+            translation: linear * self.translation + translation,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::identity()
+    }
+
+    pub fn apply_point(&self, point: [f32; 3]) -> [f32; 3] {
+        (self.linear * Vector3::from(point) + self.translation).into()
+    }
+
+    /// Transform the triangles in place. Mirroring turns the faces inside out, so
+    /// the winding is reversed whenever the transformation does.
+    pub fn apply(&self, triangles: &mut [Triangle]) {
+        let flip = self.linear.determinant() < 0.0;
+        triangles.par_iter_mut().for_each(|triangle| {
+            let [a, b, c] = triangle.vertices.map(|v| self.apply_point(v));
+            triangle.vertices = if flip { [a, c, b] } else { [a, b, c] };
+        });
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::HashMap;
+
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 #[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
 pub struct CalculateVolumeReq {
@@ -31,6 +33,305 @@ pub struct CalculateVolumeReq {
         message = "unit must be one of 'mm', 'cm', or 'm'"
     ))]
     pub unit: String,
+
+    /// number of copies ordered
+    #[schema(example = 4)]
+    #[serde(default = "default_quantity")]
+    #[validate(range(min = 1, max = 500, message = "quantity must be between 1 and 500"))]
+    pub quantity: u32,
+
+    /// optional pricing, when given the response includes a quote for `quantity`
+    /// copies
+    #[validate(nested)]
+    pub quote: Option<QuoteReq>,
+
+    /// optional transformation applied to the model before anything but the
+    /// fingerprint is calculated
+    #[validate(nested)]
+    pub transform: Option<TransformReq>,
+
+    /// whether the geometry fingerprint should ignore where the model sits in
+    /// space, so moved copies of the same model share a fingerprint
+    #[schema(example = false)]
+    #[serde(default)]
+    pub translation_invariant: bool,
+
+    /// also compute the convex hull of the model
+    #[schema(example = false)]
+    #[serde(default)]
+    pub convex_hull: bool,
+
+    /// look for sealed internal voids, which trap uncured resin in SLA prints
+    #[schema(example = false)]
+    #[serde(default)]
+    pub detect_cavities: bool,
+
+    /// optional SLA hollowing, when given the response includes the resin needed
+    /// for the hollowed part
+    #[validate(nested)]
+    pub hollow: Option<HollowReq>,
+
+    /// optional FFF print settings, when given the response includes a slicing
+    /// based material and print time estimate
+    #[validate(nested)]
+    pub print: Option<PrintSettingsReq>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct QuoteReq {
+    /// price of one cm³ of material
+    #[schema(example = 0.12)]
+    #[validate(range(min = 0.0, message = "material_price must not be negative"))]
+    pub material_price: f32,
+
+    /// price of one cm³ per material name, for multi-material models, materials not
+    /// listed use `material_price`
+    #[schema(example = json!({"red_pla": 0.15}))]
+    #[serde(default)]
+    #[validate(custom(function = "validate_material_prices"))]
+    pub material_prices: HashMap<String, f32>,
+
+    /// price charged once per job
+    #[schema(example = 5.0)]
+    #[serde(default)]
+    #[validate(range(min = 0.0, message = "setup_fee must not be negative"))]
+    pub setup_fee: f32,
+
+    /// price charged for every build plate the copies are nested onto
+    #[schema(example = 2.5)]
+    #[serde(default)]
+    #[validate(range(min = 0.0, message = "plate_fee must not be negative"))]
+    pub plate_fee: f32,
+
+    /// build plate width (X) in mm
+    #[schema(example = 220.0)]
+    #[validate(range(
+        min = 10.0,
+        max = 2000.0,
+        message = "bed_width must be between 10 and 2000 mm"
+    ))]
+    pub bed_width: f32,
+
+    /// build plate depth (Y) in mm
+    #[schema(example = 220.0)]
+    #[validate(range(
+        min = 10.0,
+        max = 2000.0,
+        message = "bed_depth must be between 10 and 2000 mm"
+    ))]
+    pub bed_depth: f32,
+
+    /// gap kept between parts and around the plate edge in mm
+    #[schema(example = 5.0)]
+    #[serde(default = "default_spacing")]
+    #[validate(range(min = 0.0, max = 50.0, message = "spacing must be between 0 and 50 mm"))]
+    pub spacing: f32,
+}
+
+/// Scale, mirror, rotation and translation, applied in that order. Rotation and
+/// mirroring happen about the origin of the model file.
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+#[validate(schema(function = "validate_transform", skip_on_field_errors = false))]
+pub struct TransformReq {
+    /// a single factor for all axes or one per axis
+    #[schema(example = 1.5)]
+    #[serde(default)]
+    pub scale: ScaleReq,
+
+    /// mirror along the X, Y and Z axes
+    #[schema(example = json!([true, false, false]))]
+    #[serde(default)]
+    pub mirror: [bool; 3],
+
+    /// rotation in degrees about the X, then Y, then Z axis
+    #[schema(example = json!([0.0, 0.0, 90.0]))]
+    #[serde(default)]
+    pub rotation: [f32; 3],
+
+    /// translation in mm along the X, Y and Z axes
+    #[schema(example = json!([0.0, 0.0, 10.0]))]
+    #[serde(default)]
+    pub translation: [f32; 3],
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum ScaleReq {
+    Uniform(f32),
+    PerAxis([f32; 3]),
+}
+
+impl Default for ScaleReq {
+    fn default() -> Self {
+        Self::Uniform(1.0)
+    }
+}
+
+impl ScaleReq {
+    pub fn factors(&self) -> [f32; 3] {
+        match *self {
+            Self::Uniform(factor) => [factor; 3],
+            Self::PerAxis(factors) => factors,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+#[validate(schema(function = "validate_layer_bounds", skip_on_field_errors = false))]
+pub struct PrintSettingsReq {
+    /// fixed layer height in mm, ignored when `adaptive_layers` is set
+    #[schema(example = 0.2)]
+    #[serde(default = "default_layer_height")]
+    #[validate(range(
+        min = 0.02,
+        max = 1.0,
+        message = "layer_height must be between 0.02 and 1 mm"
+    ))]
+    pub layer_height: f32,
+
+    /// pick every layer height from the slope of the surrounding faces, within
+    /// `min_layer_height` and `max_layer_height`
+    #[schema(example = true)]
+    #[serde(default)]
+    pub adaptive_layers: bool,
+
+    /// thinnest adaptive layer in mm, used on shallow slopes
+    #[schema(example = 0.08)]
+    #[serde(default = "default_min_layer_height")]
+    #[validate(range(
+        min = 0.02,
+        max = 1.0,
+        message = "min_layer_height must be between 0.02 and 1 mm"
+    ))]
+    pub min_layer_height: f32,
+
+    /// thickest adaptive layer in mm, used on vertical walls
+    #[schema(example = 0.3)]
+    #[serde(default = "default_max_layer_height")]
+    #[validate(range(
+        min = 0.02,
+        max = 1.0,
+        message = "max_layer_height must be between 0.02 and 1 mm"
+    ))]
+    pub max_layer_height: f32,
+
+    /// extrusion width in mm
+    #[schema(example = 0.4)]
+    #[serde(default = "default_line_width")]
+    #[validate(range(
+        min = 0.1,
+        max = 2.0,
+        message = "line_width must be between 0.1 and 2 mm"
+    ))]
+    pub line_width: f32,
+
+    /// number of perimeters printed around every layer
+    #[schema(example = 2)]
+    #[serde(default = "default_wall_count")]
+    #[validate(range(min = 0, max = 20, message = "wall_count must be between 0 and 20"))]
+    pub wall_count: u32,
+
+    /// fraction of the interior filled with infill, between 0 and 1
+    #[schema(example = 0.2)]
+    #[serde(default = "default_infill_density")]
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "infill_density must be between 0 and 1"
+    ))]
+    pub infill_density: f32,
+
+    /// print speed in mm/s
+    #[schema(example = 60.0)]
+    #[serde(default = "default_print_speed")]
+    #[validate(range(
+        min = 1.0,
+        max = 1000.0,
+        message = "print_speed must be between 1 and 1000 mm/s"
+    ))]
+    pub print_speed: f32,
+
+    /// thickness of the solid top and bottom surfaces in mm
+    #[schema(example = 0.8)]
+    #[serde(default = "default_skin_thickness")]
+    #[validate(range(
+        min = 0.0,
+        max = 10.0,
+        message = "skin_thickness must be between 0 and 10 mm"
+    ))]
+    pub skin_thickness: f32,
+
+    /// optional bed adhesion features, estimated as separate line items
+    #[validate(nested)]
+    pub adhesion: Option<AdhesionReq>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct HollowReq {
+    /// thickness in mm of the shell left around the hollow
+    #[schema(example = 2.0)]
+    #[serde(default = "default_wall_thickness")]
+    #[validate(range(
+        min = 0.5,
+        max = 20.0,
+        message = "wall_thickness must be between 0.5 and 20 mm"
+    ))]
+    pub wall_thickness: f32,
+
+    /// diameter in mm of the drain holes at the lowest point of every hollow, no
+    /// holes are drilled when omitted
+    #[schema(example = 3.0)]
+    #[validate(range(
+        min = 0.5,
+        max = 20.0,
+        message = "drain_diameter must be between 0.5 and 20 mm"
+    ))]
+    pub drain_diameter: Option<f32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct AdhesionReq {
+    /// brim width in mm around the outer walls of the first layer, 0 for no brim
+    #[schema(example = 5.0)]
+    #[serde(default)]
+    #[validate(range(
+        min = 0.0,
+        max = 50.0,
+        message = "brim_width must be between 0 and 50 mm"
+    ))]
+    pub brim_width: f32,
+
+    /// number of skirt loops around the part, 0 for no skirt
+    #[schema(example = 2)]
+    #[serde(default)]
+    #[validate(range(min = 0, max = 20, message = "skirt_lines must be between 0 and 20"))]
+    pub skirt_lines: u32,
+
+    /// gap in mm between the part and the innermost skirt loop
+    #[schema(example = 3.0)]
+    #[serde(default = "default_skirt_distance")]
+    #[validate(range(
+        min = 0.0,
+        max = 50.0,
+        message = "skirt_distance must be between 0 and 50 mm"
+    ))]
+    pub skirt_distance: f32,
+
+    /// number of raft layers printed under the part, 0 for no raft
+    #[schema(example = 0)]
+    #[serde(default)]
+    #[validate(range(min = 0, max = 10, message = "raft_layers must be between 0 and 10"))]
+    pub raft_layers: u32,
+
+    /// distance in mm the raft extends past the footprint of the part
+    #[schema(example = 3.0)]
+    #[serde(default = "default_raft_margin")]
+    #[validate(range(
+        min = 0.0,
+        max = 50.0,
+        message = "raft_margin must be between 0 and 50 mm"
+    ))]
+    pub raft_margin: f32,
 }
 
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
@@ -43,16 +344,746 @@ pub struct CalculateVolumeRes {
 
     #[schema(example = 12.345)]
     volume: f32,
+
+    /// geometry hash independent of facet order, equal for duplicate uploads
+    #[schema(example = "3f1c9a0e5b7d2c4a8e6f1b3d5a7c9e0f")]
+    fingerprint: String,
+
+    /// volume of every build item of 3MF files, `volume` being their total
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    items: Vec<BuildItemRes>,
+
+    /// volume per material of multi-material models
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    materials: Vec<MaterialRes>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hull: Option<HullRes>,
+
+    /// sealed internal voids, present when `detect_cavities` was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cavities: Option<Vec<CavityRes>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hollow: Option<HollowRes>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimate: Option<EstimateRes>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quote: Option<QuoteRes>,
+
+    /// why no quote could be made, e.g. when the part does not fit on the plate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quote_error: Option<String>,
 }
 
 impl CalculateVolumeRes {
-    pub fn new(triangles: usize, volume: f32) -> Self {
+    pub fn new(triangles: usize, volume: f32, fingerprint: String) -> Self {
         Self {
             status: "success".to_string(),
             triangles,
             volume,
+            fingerprint,
+            items: Vec::new(),
+            materials: Vec::new(),
+            hull: None,
+            cavities: None,
+            hollow: None,
+            estimate: None,
+            quote: None,
+            quote_error: None,
         }
     }
+
+    pub fn with_quote(mut self, quote: QuoteRes) -> Self {
+        self.quote = Some(quote);
+        self
+    }
+
+    pub fn with_quote_error(mut self, error: String) -> Self {
+        self.quote_error = Some(error);
+        self
+    }
+
+    pub fn with_cavities(mut self, cavities: Vec<CavityRes>) -> Self {
+        self.cavities = Some(cavities);
+        self
+    }
+
+    pub fn with_hollow(mut self, hollow: HollowRes) -> Self {
+        self.hollow = Some(hollow);
+        self
+    }
+
+    pub fn with_items(mut self, items: Vec<BuildItemRes>) -> Self {
+        self.items = items;
+        self
+    }
+
+    pub fn with_materials(mut self, materials: Vec<MaterialRes>) -> Self {
+        self.materials = materials;
+        self
+    }
+
+    pub fn with_hull(mut self, hull: HullRes) -> Self {
+        self.hull = Some(hull);
+        self
+    }
+
+    pub fn with_estimate(mut self, estimate: EstimateRes) -> Self {
+        self.estimate = Some(estimate);
+        self
+    }
+}
+
+/// results of a single model, or of every model of a zip archive
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum VolumeRes {
+    Model(Box<CalculateVolumeRes>),
+    Archive(CalculateArchiveRes),
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CalculateArchiveRes {
+    #[schema(example = "success")]
+    pub status: String,
+
+    /// every model of the archive, analysed on its own
+    pub files: Vec<ArchiveFileRes>,
+
+    /// files of the archive that are not models of a supported format
+    #[schema(example = json!(["README.txt"]))]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ArchiveFileRes {
+    /// path of the model inside the archive
+    #[schema(example = "parts/bracket.stl")]
+    pub file_name: String,
+
+    #[schema(example = "stl")]
+    pub format: String,
+
+    /// results of the model, when it could be analysed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<CalculateVolumeRes>,
+
+    /// why the model could not be analysed
+    #[schema(example = "invalid OBJ file: face refers to a missing vertex")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct HullRes {
+    /// volume enclosed by the convex hull, in the requested unit cubed
+    #[schema(example = 18.5)]
+    pub volume: f32,
+
+    /// surface area of the convex hull, in the requested unit squared
+    #[schema(example = 42.7)]
+    pub area: f32,
+
+    /// hull volume divided by the model volume, 1 for convex models
+    #[schema(example = 1.5)]
+    pub volume_ratio: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CavityRes {
+    /// enclosed volume, in the requested unit cubed
+    #[schema(example = 1.25)]
+    pub volume: f32,
+
+    /// lower corner of the cavity bounding box in model coordinates (mm)
+    #[schema(example = json!([10.0, 12.5, 4.0]))]
+    pub min: [f32; 3],
+
+    /// upper corner of the cavity bounding box in model coordinates (mm)
+    #[schema(example = json!([20.0, 22.5, 14.0]))]
+    pub max: [f32; 3],
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct HollowRes {
+    /// resin needed for the hollowed part, in the requested unit cubed
+    #[schema(example = 4.2)]
+    pub resin_volume: f32,
+
+    /// resin needed for the part printed solid, in the requested unit cubed
+    #[schema(example = 12.345)]
+    pub solid_volume: f32,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drain_holes: Vec<DrainHoleRes>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct DrainHoleRes {
+    /// where the hole leaves the outer surface, in model coordinates (mm)
+    #[schema(example = json!([15.0, 17.5, 0.0]))]
+    pub position: [f32; 3],
+
+    /// length of the hole through the wall in mm
+    #[schema(example = 2.0)]
+    pub depth: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BuildItemRes {
+    /// name of the placed object, when the file gives one
+    #[schema(example = "bracket")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// id of the placed object in the file
+    #[schema(example = 1)]
+    pub object: u32,
+
+    #[schema(example = 6.17)]
+    pub volume: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct MaterialRes {
+    /// material name as used by the model file, colours are named by their hex code
+    #[schema(example = "red_pla")]
+    pub name: String,
+
+    /// volume made of this material, in the requested unit cubed
+    #[schema(example = 4.56)]
+    pub volume: f32,
+
+    /// surface covered by this material, in the requested unit squared
+    #[schema(example = 17.3)]
+    pub area: f32,
+
+    /// RGBA colour from 0 to 1, when the file defines one
+    #[schema(example = json!([0.8, 0.1, 0.1, 1.0]))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<[f32; 4]>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct QuoteRes {
+    /// number of copies quoted
+    #[schema(example = 4)]
+    pub quantity: u32,
+
+    /// build plates needed to print all copies
+    #[schema(example = 1)]
+    pub plates: usize,
+
+    /// per-copy cost of every material of multi-material models
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub materials: Vec<MaterialCostRes>,
+
+    /// cost of a single copy, charged for every copy
+    #[schema(example = 1.48)]
+    pub per_copy: f32,
+
+    /// setup and plate preparation, charged once for the job
+    #[schema(example = 7.5)]
+    pub per_job: f32,
+
+    /// total price of the job
+    #[schema(example = 13.42)]
+    pub total: f32,
+
+    /// total divided by the quantity
+    #[schema(example = 3.36)]
+    pub unit_price: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct MaterialCostRes {
+    #[schema(example = "red_pla")]
+    pub name: String,
+
+    /// cost of this material in a single copy
+    #[schema(example = 0.68)]
+    pub per_copy: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct EstimateRes {
+    /// number of printed layers
+    #[schema(example = 412)]
+    pub layers: usize,
+
+    /// thinnest layer of the stack in mm
+    #[schema(example = 0.08)]
+    pub min_layer_height: f32,
+
+    /// thickest layer of the stack in mm
+    #[schema(example = 0.3)]
+    pub max_layer_height: f32,
+
+    /// extruded material, in the requested unit cubed
+    #[schema(example = 7.89)]
+    pub material_volume: f32,
+
+    /// part of `material_volume` printed as solid top and bottom skin
+    #[schema(example = 2.15)]
+    pub skin_volume: f32,
+
+    /// estimated print time in seconds
+    #[schema(example = 5_400.0)]
+    pub print_time: f32,
+
+    /// bed adhesion features, not included in the totals above
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub adhesion: Vec<LineItemRes>,
+
+    /// unsupported regions printed as bridges, their slower speed is included in the
+    /// print time
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bridges: Vec<BridgeRes>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BridgeRes {
+    /// height of the layer from the bottom of the model in mm
+    #[schema(example = 12.4)]
+    pub z: f32,
+
+    /// bridged area, in the requested unit squared
+    #[schema(example = 48.0)]
+    pub area: f32,
+
+    /// distance between the anchors, in the requested unit
+    #[schema(example = 12.0)]
+    pub span: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct LineItemRes {
+    /// "brim", "skirt" or "raft"
+    #[schema(example = "brim")]
+    pub name: String,
+
+    /// extruded material, in the requested unit cubed
+    #[schema(example = 0.42)]
+    pub material_volume: f32,
+
+    /// estimated print time in seconds
+    #[schema(example = 95.0)]
+    pub print_time: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct NestingReq {
+    /// 26-character order ID obtained when the objects are uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX5T5F4FH")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub order_id: String,
+
+    /// items of the order to lay out on the build plates
+    #[validate(
+        length(
+            min = 1,
+            max = 20,
+            message = "items must contain between 1 and 20 entries"
+        ),
+        nested
+    )]
+    pub items: Vec<NestingItem>,
+
+    /// build plate width (X) in mm
+    #[schema(example = 220.0)]
+    #[validate(range(
+        min = 10.0,
+        max = 2000.0,
+        message = "bed_width must be between 10 and 2000 mm"
+    ))]
+    pub bed_width: f32,
+
+    /// build plate depth (Y) in mm
+    #[schema(example = 220.0)]
+    #[validate(range(
+        min = 10.0,
+        max = 2000.0,
+        message = "bed_depth must be between 10 and 2000 mm"
+    ))]
+    pub bed_depth: f32,
+
+    /// gap kept between parts and around the plate edge in mm
+    #[schema(example = 5.0)]
+    #[serde(default = "default_spacing")]
+    #[validate(range(min = 0.0, max = 50.0, message = "spacing must be between 0 and 50 mm"))]
+    pub spacing: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct NestingItem {
+    /// 26-character item ID obtained when the object is uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX9NJ47AR")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub item_id: String,
+
+    /// file name with extension obtained when the object is uploaded to S3
+    #[schema(example = "model_file.stl")]
+    #[validate(regex(
+        path = "*FILENAME_REGEX",
+        message = "file_name must be alphanumeric characters with hyphens, periods, or underscores only"
+    ))]
+    pub file_name: String,
+
+    /// number of copies to print
+    #[schema(example = 4)]
+    #[validate(range(min = 1, max = 500, message = "quantity must be between 1 and 500"))]
+    pub quantity: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NestingRes {
+    #[schema(example = "success")]
+    status: String,
+
+    /// number of build plates needed to print every copy
+    #[schema(example = 2)]
+    plates: usize,
+
+    placements: Vec<PlacementRes>,
+}
+
+impl NestingRes {
+    pub fn new(plates: usize, placements: Vec<PlacementRes>) -> Self {
+        Self {
+            status: "success".to_string(),
+            plates,
+            placements,
+        }
+    }
+}
+
+/// position of a single copy, rotate the model about Z by `rotation` degrees
+/// and then translate it by `offset` (mm) to put it in place on its plate
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct PlacementRes {
+    #[schema(example = "01K9N559GM0BXKW00QX9NJ47AR")]
+    pub item_id: String,
+
+    /// zero-based copy number of the item
+    #[schema(example = 0)]
+    pub copy: u32,
+
+    /// zero-based build plate index
+    #[schema(example = 0)]
+    pub plate: usize,
+
+    #[schema(example = 90.0)]
+    pub rotation: f32,
+
+    #[schema(example = json!([12.5, 40.0]))]
+    pub offset: [f32; 2],
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct PreviewReq {
+    /// 26-character order ID obtained when the object is uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX5T5F4FH")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub order_id: String,
+
+    /// 26-character item ID obtained when the object is uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX9NJ47AR")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub item_id: String,
+
+    /// file name with extension obtained when the object is uploaded to S3
+    #[schema(example = "model_file.stl")]
+    #[validate(regex(
+        path = "*FILENAME_REGEX",
+        message = "file_name must be alphanumeric characters with hyphens, periods, or underscores only"
+    ))]
+    pub file_name: String,
+
+    /// maximum number of triangles in the preview mesh
+    #[schema(example = 100_000)]
+    #[serde(default = "default_preview_triangles")]
+    #[validate(range(
+        min = 100,
+        max = 2_000_000,
+        message = "target_triangles must be between 100 and 2000000"
+    ))]
+    pub target_triangles: u32,
+
+    /// optional maximum deviation from the original surface in mm, simplification
+    /// stops early once it is reached
+    #[schema(example = 0.05)]
+    #[validate(range(
+        min = 0.0,
+        max = 10.0,
+        message = "max_error must be between 0 and 10 mm"
+    ))]
+    pub max_error: Option<f32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+pub struct ThumbnailReq {
+    /// 26-character order ID obtained when the object is uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX5T5F4FH")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub order_id: String,
+
+    /// 26-character item ID obtained when the object is uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX9NJ47AR")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub item_id: String,
+
+    /// file name with extension obtained when the object is uploaded to S3
+    #[schema(example = "model_file.stl")]
+    #[validate(regex(
+        path = "*FILENAME_REGEX",
+        message = "file_name must be alphanumeric characters with hyphens, periods, or underscores only"
+    ))]
+    pub file_name: String,
+
+    /// image width in pixels
+    #[schema(example = 512)]
+    #[serde(default = "default_image_size")]
+    #[validate(range(min = 16, max = 2048, message = "width must be between 16 and 2048"))]
+    pub width: u32,
+
+    /// image height in pixels
+    #[schema(example = 512)]
+    #[serde(default = "default_image_size")]
+    #[validate(range(min = 16, max = 2048, message = "height must be between 16 and 2048"))]
+    pub height: u32,
+
+    /// camera rotation around the vertical axis in degrees
+    #[schema(example = 45.0)]
+    #[serde(default = "default_yaw")]
+    #[validate(range(min = -360.0, max = 360.0, message = "yaw must be between -360 and 360 degrees"))]
+    pub yaw: f32,
+
+    /// camera elevation above the build plate in degrees
+    #[schema(example = 30.0)]
+    #[serde(default = "default_pitch")]
+    #[validate(range(min = -90.0, max = 90.0, message = "pitch must be between -90 and 90 degrees"))]
+    pub pitch: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, ToSchema)]
+#[validate(schema(
+    function = "validate_cross_section_heights",
+    skip_on_field_errors = false
+))]
+pub struct CrossSectionReq {
+    /// 26-character order ID obtained when the object is uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX5T5F4FH")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub order_id: String,
+
+    /// 26-character item ID obtained when the object is uploaded to S3
+    #[schema(example = "01K9N559GM0BXKW00QX9NJ47AR")]
+    #[validate(length(equal = 26, message = "must be 26 characters long"))]
+    pub item_id: String,
+
+    /// file name with extension obtained when the object is uploaded to S3
+    #[schema(example = "model_file.stl")]
+    #[validate(regex(
+        path = "*FILENAME_REGEX",
+        message = "file_name must be alphanumeric characters with hyphens, periods, or underscores only"
+    ))]
+    pub file_name: String,
+
+    /// height of the section in mm above the lowest point of the model,
+    /// mutually exclusive with `count`
+    #[schema(example = 12.4)]
+    #[validate(range(min = 0.0, message = "z must not be negative"))]
+    pub z: Option<f32>,
+
+    /// number of evenly spaced sections through the whole model,
+    /// mutually exclusive with `z`
+    #[schema(example = 10)]
+    #[validate(range(min = 1, max = 100, message = "count must be between 1 and 100"))]
+    pub count: Option<u32>,
+
+    /// image format: "png" or "svg"
+    #[schema(example = "png")]
+    #[serde(default = "default_section_format")]
+    #[validate(regex(
+        path = "*SECTION_FORMAT_REGEX",
+        message = "format must be one of 'png' or 'svg'"
+    ))]
+    pub format: String,
+
+    /// size in pixels of the longest side of PNG images
+    #[schema(example = 512)]
+    #[serde(default = "default_image_size")]
+    #[validate(range(min = 16, max = 2048, message = "size must be between 16 and 2048"))]
+    pub size: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CrossSectionRes {
+    #[schema(example = "success")]
+    status: String,
+
+    /// "image/png" or "image/svg+xml"
+    #[schema(example = "image/png")]
+    content_type: String,
+
+    sections: Vec<SectionRes>,
+}
+
+impl CrossSectionRes {
+    pub fn new(content_type: impl Into<String>, sections: Vec<SectionRes>) -> Self {
+        Self {
+            status: "success".to_string(),
+            content_type: content_type.into(),
+            sections,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct SectionRes {
+    /// height of the section in mm above the lowest point of the model
+    #[schema(example = 12.4)]
+    pub z: f32,
+
+    /// filled area of the section in mm²
+    #[schema(example = 153.9)]
+    pub area: f32,
+
+    /// number of closed outlines (outer walls and holes) in the section
+    #[schema(example = 2)]
+    pub contours: usize,
+
+    /// the image, base64 encoded for PNG and plain text for SVG
+    pub data: String,
+}
+
+fn validate_material_prices(prices: &HashMap<String, f32>) -> Result<(), ValidationError> {
+    if prices.len() > 64 || prices.values().any(|price| price.is_nan() || *price < 0.0) {
+        return Err(ValidationError::new("material_prices")
+            .with_message("at most 64 material prices, none of them negative".into()));
+    }
+    Ok(())
+}
+
+fn validate_transform(req: &TransformReq) -> Result<(), ValidationError> {
+    if !req
+        .scale
+        .factors()
+        .iter()
+        .all(|factor| (0.001..=1000.0).contains(factor))
+    {
+        return Err(ValidationError::new("scale")
+            .with_message("scale factors must be between 0.001 and 1000".into()));
+    }
+    if !req
+        .rotation
+        .iter()
+        .all(|angle| (-360.0..=360.0).contains(angle))
+    {
+        return Err(ValidationError::new("rotation")
+            .with_message("rotation angles must be between -360 and 360 degrees".into()));
+    }
+    if !req
+        .translation
+        .iter()
+        .all(|offset| (-10_000.0..=10_000.0).contains(offset))
+    {
+        return Err(ValidationError::new("translation")
+            .with_message("translation must be between -10000 and 10000 mm".into()));
+    }
+    Ok(())
+}
+
+fn validate_layer_bounds(req: &PrintSettingsReq) -> Result<(), ValidationError> {
+    if req.min_layer_height > req.max_layer_height {
+        return Err(ValidationError::new("layer_bounds")
+            .with_message("min_layer_height must not be greater than max_layer_height".into()));
+    }
+    Ok(())
+}
+
+fn validate_cross_section_heights(req: &CrossSectionReq) -> Result<(), ValidationError> {
+    match (req.z, req.count) {
+        (Some(_), None) | (None, Some(_)) => Ok(()),
+        _ => Err(ValidationError::new("heights")
+            .with_message("exactly one of z or count must be provided".into())),
+    }
+}
+
+fn default_layer_height() -> f32 {
+    0.2
+}
+
+fn default_min_layer_height() -> f32 {
+    0.08
+}
+
+fn default_max_layer_height() -> f32 {
+    0.3
+}
+
+fn default_line_width() -> f32 {
+    0.4
+}
+
+fn default_wall_count() -> u32 {
+    2
+}
+
+fn default_infill_density() -> f32 {
+    0.2
+}
+
+fn default_print_speed() -> f32 {
+    60.0
+}
+
+fn default_skin_thickness() -> f32 {
+    0.8
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+fn default_wall_thickness() -> f32 {
+    2.0
+}
+
+fn default_skirt_distance() -> f32 {
+    3.0
+}
+
+fn default_raft_margin() -> f32 {
+    3.0
+}
+
+fn default_spacing() -> f32 {
+    5.0
+}
+
+fn default_preview_triangles() -> u32 {
+    100_000
+}
+
+fn default_image_size() -> u32 {
+    512
+}
+
+fn default_section_format() -> String {
+    "png".to_string()
+}
+
+fn default_yaw() -> f32 {
+    45.0
+}
+
+fn default_pitch() -> f32 {
+    30.0
 }
 
 // file_name: only alphanumeric, hyphens, periods and underscores
@@ -60,3 +1091,6 @@ static FILENAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z0-9_.-]+$
 
 // unit: only "mm", "cm", or "m"
 static UNIT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(mm|cm|m)$").unwrap());
+
+// format: only "png" or "svg"
+static SECTION_FORMAT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(png|svg)$").unwrap());
@@ -0,0 +1,78 @@
+pub mod section;
+pub mod thumbnail;
+
+use crate::error::AppError;
+
+/// 8-bit RGBA image, rows stored top to bottom
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, background: [u8; 4]) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+        }
+    }
+
+    /// box filter the image down by an integer factor
+    pub fn downsample(&self, factor: u32) -> Self {
+        if factor <= 1 {
+            return self.clone();
+        }
+
+        let (width, height) = (self.width / factor, self.height / factor);
+        let samples = factor * factor;
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let mut sum = [0u32; 4];
+                for sy in 0..factor {
+                    for sx in 0..factor {
+                        let index = ((y * factor + sy) * self.width + x * factor + sx) as usize;
+                        let pixel = self.pixels[index];
+                        // premultiply so transparent samples do not darken the edges
+                        let alpha = pixel[3] as u32;
+                        for c in 0..3 {
+                            sum[c] += pixel[c] as u32 * alpha;
+                        }
+                        sum[3] += alpha;
+                    }
+                }
+                if sum[3] == 0 {
+                    return [0, 0, 0, 0];
+                }
+                [
+                    (sum[0] / sum[3]) as u8,
+                    (sum[1] / sum[3]) as u8,
+                    (sum[2] / sum[3]) as u8,
+                    (sum[3] / samples) as u8,
+                ]
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, AppError> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(self.pixels.as_flattened())?;
+        writer.finish()?;
+
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write;
+
+use crate::{
+    calculate::slice::{Grid, Layer},
+    render::Image,
+};
+
+const FILL: [u8; 4] = [40, 90, 160, 255];
+const BACKGROUND: [u8; 4] = [255, 255, 255, 255];
+
+/// Filled cross-section as an image, the grid decides the framing so several
+/// sections of the same model line up.
+pub fn png(layer: &Layer, grid: &Grid) -> Image {
+    let coverage = layer.coverage(grid);
+    let mut image = Image::new(grid.width as u32, grid.height as u32, BACKGROUND);
+
+    // grid rows grow with Y while image rows grow downwards
+    for (row, cells) in coverage.chunks_exact(grid.width).enumerate() {
+        let target = (grid.height - 1 - row) * grid.width;
+        for (x, &inside) in cells.iter().enumerate() {
+            if inside {
+                image.pixels[target + x] = FILL;
+            }
+        }
+    }
+
+    image
+}
+
+/// Filled cross-section as an SVG document in mm, framed by the grid.
+pub fn svg(layer: &Layer, grid: &Grid) -> String {
+    let width = grid.width as f32 * grid.cell;
+    let height = grid.height as f32 * grid.cell;
+    let top = grid.origin[1] + height;
+
+    let mut path = String::new();
+    for contour in layer.contours.iter() {
+        for (i, p) in contour.iter().enumerate() {
+            let _ = write!(
+                path,
+                "{}{:.4},{:.4} ",
+                if i == 0 { "M" } else { "L" },
+                p[0] - grid.origin[0],
+                top - p[1],
+            );
+        }
+        path.push_str("Z ");
+    }
+
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}mm" height="{h}mm" viewBox="0 0 {w} {h}">"#,
+            r#"<rect width="{w}" height="{h}" fill="rgb({br},{bg},{bb})"/>"#,
+            r#"<path d="{path}" fill="rgb({fr},{fg},{fb})" fill-rule="evenodd"/>"#,
+            "</svg>"
+        ),
+        w = width,
+        h = height,
+        br = BACKGROUND[0],
+        bg = BACKGROUND[1],
+        bb = BACKGROUND[2],
+        path = path.trim_end(),
+        fr = FILL[0],
+        fg = FILL[1],
+        fb = FILL[2],
+    )
+}
@@ -0,0 +1,174 @@
+// CPU thumbnail renderer
+//
+// Orthographic camera orbiting the centre of the model, triangles are rasterised
+// with a depth buffer and flat Lambert shading. The image is split into horizontal
+// bands rendered in parallel, each band only visiting the triangles overlapping it.
+
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use crate::{calculate, model, render::Image};
+
+/// rows rendered per parallel job
+const BAND_HEIGHT: usize = 16;
+/// samples per pixel along each axis
+const SUPERSAMPLE: u32 = 2;
+
+const BASE_COLOR: [f32; 3] = [0.55, 0.62, 0.72];
+const AMBIENT: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    /// rotation of the camera around the Z axis in degrees, 0 looks along -X
+    pub yaw: f32,
+    /// elevation of the camera above the XY plane in degrees
+    pub pitch: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            yaw: 45.0,
+            pitch: 30.0,
+            width: 512,
+            height: 512,
+        }
+    }
+}
+
+struct Projected {
+    points: [[f32; 3]; 3],
+    color: [u8; 4],
+    min_y: usize,
+    max_y: usize,
+}
+
+pub fn render(triangles: &[model::Triangle], camera: &Camera) -> Image {
+    let width = camera.width * SUPERSAMPLE;
+    let height = camera.height * SUPERSAMPLE;
+    let mut image = Image::new(width, height, [0, 0, 0, 0]);
+    if triangles.is_empty() {
+        return image.downsample(SUPERSAMPLE);
+    }
+
+    let (min, max) = calculate::bounds(triangles);
+    let (min, max) = (Vector3::from(min), Vector3::from(max));
+    let center = (min + max) / 2.0;
+    let radius = ((max - min).norm() / 2.0).max(f32::EPSILON);
+
+    // camera frame, `toward` points from the model to the camera
+    let (yaw, pitch) = (camera.yaw.to_radians(), camera.pitch.to_radians());
+    let toward = Vector3::new(
+        pitch.cos() * yaw.cos(),
+        pitch.cos() * yaw.sin(),
+        pitch.sin(),
+    );
+    let right = Vector3::z()
+        .cross(&toward)
+        .try_normalize(1e-6)
+        .unwrap_or_else(Vector3::y);
+    let up = toward.cross(&right);
+    let light = (toward + up * 0.6 - right * 0.4).normalize();
+
+    let scale = width.min(height) as f32 * 0.95 / (2.0 * radius);
+    let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+
+    let projected: Vec<Projected> = triangles
+        .par_iter()
+        .filter_map(|t| {
+            let [a, b, c] = t.vertices().map(Vector3::from);
+            let normal = (b - a).cross(&(c - a)).try_normalize(0.0)?;
+            // two sided, the winding of uploaded models cannot be trusted
+            let intensity = AMBIENT + (1.0 - AMBIENT) * normal.dot(&light).abs();
+            let color = BASE_COLOR.map(|c| (c * intensity * 255.0).round().min(255.0) as u8);
+
+            let points = [a, b, c].map(|p| {
+                let p = p - center;
+                [
+                    half_width + p.dot(&right) * scale,
+                    half_height - p.dot(&up) * scale,
+                    p.dot(&toward),
+                ]
+            });
+            let min_y = points.iter().map(|p| p[1]).fold(f32::INFINITY, f32::min);
+            let max_y = points
+                .iter()
+                .map(|p| p[1])
+                .fold(f32::NEG_INFINITY, f32::max);
+            if max_y < 0.0 || min_y >= height as f32 {
+                return None;
+            }
+
+            Some(Projected {
+                points,
+                color: [color[0], color[1], color[2], 255],
+                min_y: min_y.max(0.0) as usize,
+                max_y: (max_y.ceil() as usize).min(height as usize - 1),
+            })
+        })
+        .collect();
+
+    image
+        .pixels
+        .par_chunks_mut(width as usize * BAND_HEIGHT)
+        .enumerate()
+        .for_each(|(band, pixels)| {
+            let top = band * BAND_HEIGHT;
+            let rows = pixels.len() / width as usize;
+            let mut depth = vec![f32::NEG_INFINITY; pixels.len()];
+
+            for triangle in projected
+                .iter()
+                .filter(|t| t.max_y >= top && t.min_y < top + rows)
+            {
+                rasterize(triangle, pixels, &mut depth, width as usize, top, rows);
+            }
+        });
+
+    image.downsample(SUPERSAMPLE)
+}
+
+fn rasterize(
+    triangle: &Projected,
+    pixels: &mut [[u8; 4]],
+    depth: &mut [f32],
+    width: usize,
+    top: usize,
+    rows: usize,
+) {
+    let [a, b, c] = triangle.points;
+    let area = edge(&a, &b, &c);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+
+    let min_x = a[0].min(b[0]).min(c[0]).max(0.0) as usize;
+    let max_x = (a[0].max(b[0]).max(c[0]).ceil().max(0.0) as usize).min(width - 1);
+    let first = triangle.min_y.max(top);
+    let last = triangle.max_y.min(top + rows - 1);
+
+    for y in first..=last {
+        for x in min_x..=max_x {
+            let p = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+            let w0 = edge(&b, &c, &p) / area;
+            let w1 = edge(&c, &a, &p) / area;
+            let w2 = edge(&a, &b, &p) / area;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+
+            let z = w0 * a[2] + w1 * b[2] + w2 * c[2];
+            let index = (y - top) * width + x;
+            if z > depth[index] {
+                depth[index] = z;
+                pixels[index] = triangle.color;
+            }
+        }
+    }
+}
+
+fn edge(a: &[f32; 3], b: &[f32; 3], p: &[f32; 3]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}